@@ -1,18 +1,33 @@
 use anyhow::{Result, Context};
-use std::process::Command;
+use std::process::Stdio;
+use tokio::process::Command;
 
+#[derive(Clone)]
 pub struct ActionHandler;
 
+impl Default for ActionHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Actions run their commands asynchronously so a hung tool never blocks a
+// runtime worker. Each child is killed if the action's future is dropped,
+// which is how timeouts and client disconnects cancel an action.
+
 impl ActionHandler {
     pub fn new() -> Self {
         Self
     }
 
     // Helper method for safer command execution
-    fn execute_command(&self, cmd: &str, args: &[&str]) -> Result<()> {
+    async fn execute_command(&self, cmd: &str, args: &[&str]) -> Result<()> {
         let output = Command::new(cmd)
             .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
             .output()
+            .await
             .with_context(|| format!("Failed to execute command: {} {}", cmd, args.join(" ")))?;
 
         if !output.status.success() {
@@ -28,11 +43,13 @@ impl ActionHandler {
     }
 
     // Helper method for commands that return output
-    #[allow(dead_code)]
-    fn execute_command_with_output(&self, cmd: &str, args: &[&str]) -> Result<String> {
+    async fn execute_command_with_output(&self, cmd: &str, args: &[&str]) -> Result<String> {
         let output = Command::new(cmd)
             .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
             .output()
+            .await
             .with_context(|| format!("Failed to execute command: {} {}", cmd, args.join(" ")))?;
 
         if !output.status.success() {
@@ -47,60 +64,60 @@ impl ActionHandler {
     }
 
     // Power Controls
-    pub fn logout(&self) -> Result<()> {
+    pub async fn logout(&self) -> Result<()> {
         let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
-        self.execute_command("loginctl", &["terminate-user", &user])
+        self.execute_command("loginctl", &["terminate-user", &user]).await
     }
 
-    pub fn reboot(&self) -> Result<()> {
-        self.execute_command("systemctl", &["reboot"])
+    pub async fn reboot(&self) -> Result<()> {
+        self.execute_command("systemctl", &["reboot"]).await
     }
 
-    pub fn shutdown(&self) -> Result<()> {
-        self.execute_command("systemctl", &["poweroff"])
+    pub async fn shutdown(&self) -> Result<()> {
+        self.execute_command("systemctl", &["poweroff"]).await
     }
 
     // Toggle Controls
-    pub fn toggle_wifi(&self) -> Result<bool> {
-        let current_status = self.check_wifi_status()?;
+    pub async fn toggle_wifi(&self) -> Result<bool> {
+        let current_status = self.check_wifi_status().await?;
         let new_status = if current_status { "off" } else { "on" };
         
-        self.execute_command("nmcli", &["radio", "wifi", new_status])?;
+        self.execute_command("nmcli", &["radio", "wifi", new_status]).await?;
         Ok(!current_status)
     }
 
-    pub fn toggle_bluetooth(&self) -> Result<bool> {
-        let current_status = self.check_bluetooth_status()?;
+    pub async fn toggle_bluetooth(&self) -> Result<bool> {
+        let current_status = self.check_bluetooth_status().await?;
         let new_status = if current_status { "off" } else { "on" };
 
-        self.execute_command("bluetoothctl", &["power", new_status])?;
+        self.execute_command("bluetoothctl", &["power", new_status]).await?;
         Ok(!current_status)
     }
 
-    pub fn toggle_vpn(&self, vpn_name: &str) -> Result<bool> {
-        let current_status = self.check_vpn_status(vpn_name)?;
+    pub async fn toggle_vpn(&self, vpn_name: &str) -> Result<bool> {
+        let current_status = self.check_vpn_status(vpn_name).await?;
         
         if current_status {
-            self.execute_command("nmcli", &["connection", "down", vpn_name])?;
+            self.execute_command("nmcli", &["connection", "down", vpn_name]).await?;
         } else {
-            self.execute_command("nmcli", &["connection", "up", vpn_name])?;
+            self.execute_command("nmcli", &["connection", "up", vpn_name]).await?;
         }
 
         Ok(!current_status)
     }
 
     // Volume Controls
-    pub fn set_volume(&self, percent: u8) -> Result<()> {
+    pub async fn set_volume(&self, percent: u8) -> Result<()> {
         let volume_str = format!("{}%", percent.min(100));
-        self.execute_command("pactl", &["set-sink-volume", "@DEFAULT_SINK@", &volume_str])?;
+        self.execute_command("pactl", &["set-sink-volume", "@DEFAULT_SINK@", &volume_str]).await?;
         Ok(())
     }
 
-    pub fn toggle_mute(&self) -> Result<bool> {
-        self.execute_command("pactl", &["set-sink-mute", "@DEFAULT_SINK@", "toggle"])?;
+    pub async fn toggle_mute(&self) -> Result<bool> {
+        self.execute_command("pactl", &["set-sink-mute", "@DEFAULT_SINK@", "toggle"]).await?;
 
         // Get current mute status
-        let mute_output = self.execute_command_with_output("pactl", &["get-sink-mute", "@DEFAULT_SINK@"])?;
+        let mute_output = self.execute_command_with_output("pactl", &["get-sink-mute", "@DEFAULT_SINK@"]).await?;
 
         let muted = mute_output.trim().ends_with("yes");
 
@@ -108,9 +125,10 @@ impl ActionHandler {
     }
 
     // Application Launcher
+    // Launched programs outlive the action, so they are detached rather than killed on drop
     pub fn launch_app(&self, command: &str) -> Result<()> {
         Command::new("sh")
-            .args(&["-c", command])
+            .args(["-c", command])
             .spawn()
             .context("Failed to launch application launcher")?;
         Ok(())
@@ -125,18 +143,18 @@ impl ActionHandler {
     }
 
     // Helper methods for status checking
-    fn check_wifi_status(&self) -> Result<bool> {
-        let output = self.execute_command_with_output("nmcli", &["radio", "wifi"])?;
+    async fn check_wifi_status(&self) -> Result<bool> {
+        let output = self.execute_command_with_output("nmcli", &["radio", "wifi"]).await?;
         Ok(output.trim() == "enabled")
     }
 
-    fn check_bluetooth_status(&self) -> Result<bool> {
-        let output = self.execute_command_with_output("bluetoothctl", &["show"])?;
+    async fn check_bluetooth_status(&self) -> Result<bool> {
+        let output = self.execute_command_with_output("bluetoothctl", &["show"]).await?;
         Ok(output.contains("Powered: yes"))
     }
 
-    fn check_vpn_status(&self, vpn_name: &str) -> Result<bool> {
-        let output = self.execute_command_with_output("nmcli", &["connection", "show", "--active"])?;
+    async fn check_vpn_status(&self, vpn_name: &str) -> Result<bool> {
+        let output = self.execute_command_with_output("nmcli", &["connection", "show", "--active"]).await?;
         Ok(output.contains(vpn_name))
     }
 }
\ No newline at end of file
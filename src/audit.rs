@@ -0,0 +1,418 @@
+use crate::config::{self, AuditConfig};
+use crate::daemon::{IpcCommand, IpcResponse};
+use crate::state::unix_time_ms;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use tokio::sync::{mpsc, oneshot};
+
+/// Who sent a command, from the socket's peer credentials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caller {
+    pub uid: u32,
+    pub pid: Option<i32>,
+}
+
+impl Caller {
+    /// The daemon itself, for actions it runs on its own such as scheduled reboots.
+    pub fn daemon() -> Self {
+        Self {
+            uid: nix::unistd::Uid::current().as_raw(),
+            pid: Some(std::process::id() as i32),
+        }
+    }
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Unix time in milliseconds
+    pub timestamp_ms: u64,
+    pub uid: u32,
+    pub pid: Option<i32>,
+    pub command: String,
+    /// The command's arguments as sent, with config secrets redacted
+    pub args: serde_json::Value,
+    /// `ok`, `error`, `timed_out`, `confirmation_required` or `cancelled`
+    pub result: String,
+    /// Error text, the new toggle state, or the token a power action waits on
+    pub detail: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(caller: Caller, command: &IpcCommand, response: &IpcResponse) -> Self {
+        let mut args = match serde_json::to_value(command) {
+            Ok(serde_json::Value::Object(fields)) => fields.into_iter().next().map(|(_, args)| args).unwrap_or_default(),
+            _ => serde_json::Value::Null,
+        };
+        if let IpcCommand::PatchConfig(_) = command {
+            config::redact_secrets(&mut args);
+        }
+
+        let (result, detail) = match response {
+            IpcResponse::Error(e) => ("error", Some(e.clone())),
+            IpcResponse::ActionTimedOut { timeout_ms, .. } => ("timed_out", Some(format!("killed after {} ms", timeout_ms))),
+            IpcResponse::ConfirmationRequired { token, .. } => ("confirmation_required", Some(token.clone())),
+            IpcResponse::ToggleResult(enabled) => ("ok", Some(if *enabled { "on" } else { "off" }.to_string())),
+            _ => ("ok", None),
+        };
+
+        Self {
+            timestamp_ms: unix_time_ms(),
+            uid: caller.uid,
+            pid: caller.pid,
+            command: command.name().to_string(),
+            args,
+            result: result.to_string(),
+            detail,
+        }
+    }
+
+    /// For a command dropped before it answered, e.g. because its client
+    /// disconnected; whatever it had started may still have taken effect.
+    pub fn cancelled(caller: Caller, command: &IpcCommand) -> Self {
+        Self {
+            result: "cancelled".to_string(),
+            ..Self::new(caller, command, &IpcResponse::Success)
+        }
+    }
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pid = self.pid.map(|pid| pid.to_string()).unwrap_or_else(|| "-".to_string());
+        write!(f, "{}  uid={} pid={}  {}", format_time(self.timestamp_ms), self.uid, pid, self.command)?;
+        if !self.args.is_null() {
+            write!(f, " {}", self.args)?;
+        }
+        write!(f, "  {}", self.result)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail.lines().next().unwrap_or_default())?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a command changes something on the workstation and so gets audited.
+/// Reads and overlay visibility are left out.
+pub fn is_audited(command: &IpcCommand) -> bool {
+    match command {
+        IpcCommand::SetVolume(_)
+        | IpcCommand::ToggleMute
+        | IpcCommand::ToggleWifi
+        | IpcCommand::ToggleBluetooth
+        | IpcCommand::ToggleVpn
+        | IpcCommand::Logout
+        | IpcCommand::Reboot
+        | IpcCommand::Shutdown
+        | IpcCommand::LaunchApp
+        | IpcCommand::LaunchUrl(_)
+        | IpcCommand::PatchConfig(_)
+        | IpcCommand::ReloadConfig
+        | IpcCommand::ConfirmAction(_)
+        | IpcCommand::CancelAction(_)
+        | IpcCommand::ScheduleShutdown(_)
+        | IpcCommand::ScheduleReboot(_)
+        | IpcCommand::CancelScheduled
+        | IpcCommand::StopDaemon => true,
+        IpcCommand::Hello { .. }
+        | IpcCommand::Status
+        | IpcCommand::ToggleOverlay
+        | IpcCommand::ShowOverlay
+        | IpcCommand::HideOverlay
+        | IpcCommand::RegisterOverlay { .. }
+        | IpcCommand::GetState
+        | IpcCommand::GetAudioVisualizer
+        | IpcCommand::Subscribe { .. }
+        | IpcCommand::GetConfig
+        | IpcCommand::GetHealth => false,
+    }
+}
+
+/// Records waiting for the writer thread before new ones are dropped.
+const AUDIT_QUEUE: usize = 256;
+
+/// A record on its way to the writer thread.
+struct PendingRecord {
+    config: AuditConfig,
+    record: AuditRecord,
+    /// Fires once the record is on disk, or failed to get there
+    written: Option<oneshot::Sender<()>>,
+}
+
+/// Appends records to the audit log, rotating it once it reaches
+/// `max_size_kb`. Records are only ever appended, never rewritten. The file
+/// I/O happens on a thread of its own, so a slow disk never stalls the
+/// runtime.
+#[derive(Clone)]
+pub struct AuditLog {
+    records: mpsc::Sender<PendingRecord>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuditLog {
+    /// Starts the writer thread, which runs until every handle is dropped.
+    pub fn new() -> Self {
+        let (records, mut pending) = mpsc::channel::<PendingRecord>(AUDIT_QUEUE);
+        let spawned = std::thread::Builder::new()
+            .name("vacuum-audit".to_string())
+            .spawn(move || {
+                while let Some(PendingRecord { config, record, written }) = pending.blocking_recv() {
+                    if let Err(e) = append(&config, &record) {
+                        tracing::error!("Failed to write audit record for {}: {:#}", record.command, e);
+                    }
+                    if let Some(written) = written {
+                        let _ = written.send(());
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::error!("Failed to start the audit log writer: {}", e);
+        }
+        Self { records }
+    }
+
+    /// Writes `record` if auditing is enabled and waits until it's on disk.
+    /// Failures are logged, not returned, so a full disk never blocks an action.
+    pub async fn record(&self, config: &AuditConfig, record: AuditRecord) {
+        if !config.enabled {
+            return;
+        }
+        let (written, done) = oneshot::channel();
+        let pending = PendingRecord { config: config.clone(), record, written: Some(written) };
+        if self.records.send(pending).await.is_ok() {
+            let _ = done.await;
+        }
+    }
+
+    /// Queues `record` without waiting, for callers that can't, such as `Drop`.
+    pub fn record_later(&self, config: &AuditConfig, record: AuditRecord) {
+        if !config.enabled {
+            return;
+        }
+        let command = record.command.clone();
+        let pending = PendingRecord { config: config.clone(), record, written: None };
+        if let Err(e) = self.records.try_send(pending) {
+            tracing::error!("Dropped audit record for {}: {}", command, e);
+        }
+    }
+}
+
+fn append(config: &AuditConfig, record: &AuditRecord) -> Result<()> {
+    let mut line = serde_json::to_string(record).context("Failed to serialize audit record")?;
+    line.push('\n');
+
+    let path = config.log_path();
+    if let Some(parent) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)
+            .with_context(|| format!("Failed to create audit log directory {:?}", parent))?;
+    }
+
+    let size = std::fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+    if size > 0 && size + line.len() as u64 > config.max_size_kb * 1024 {
+        rotate(&path, config.max_files)?;
+    }
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to append to audit log {:?}", path))
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    PathBuf::from(rotated)
+}
+
+/// Shifts `audit.jsonl.N` up by one, dropping the oldest, and moves the
+/// live log to `audit.jsonl.1`.
+fn rotate(path: &Path, max_files: u32) -> Result<()> {
+    for n in (1..max_files).rev() {
+        let from = rotated_path(path, n);
+        if from.exists() {
+            std::fs::rename(&from, rotated_path(path, n + 1))
+                .with_context(|| format!("Failed to rotate {:?}", from))?;
+        }
+    }
+    std::fs::rename(path, rotated_path(path, 1))
+        .with_context(|| format!("Failed to rotate {:?}", path))
+}
+
+/// Filters for `--audit-log`; every bound is optional.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    /// Unix time in milliseconds, inclusive
+    pub since_ms: Option<u64>,
+    /// Unix time in milliseconds, exclusive
+    pub until_ms: Option<u64>,
+    pub command: Option<String>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.since_ms.is_none_or(|since| record.timestamp_ms >= since)
+            && self.until_ms.is_none_or(|until| record.timestamp_ms < until)
+            && self.command.as_ref().is_none_or(|command| record.command.eq_ignore_ascii_case(command))
+    }
+}
+
+/// Reads matching records from the rotated logs and the live one, oldest first.
+pub fn query(config: &AuditConfig, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+    let path = config.log_path();
+    let mut files: Vec<PathBuf> = (1..=config.max_files).rev().map(|n| rotated_path(&path, n)).collect();
+    files.push(path);
+
+    let mut records = Vec::new();
+    for file in files {
+        let reader = match std::fs::File::open(&file) {
+            Ok(reader) => BufReader::new(reader),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to open audit log {:?}", file)),
+        };
+        for line in reader.lines() {
+            let line = line.with_context(|| format!("Failed to read audit log {:?}", file))?;
+            match serde_json::from_str::<AuditRecord>(&line) {
+                Ok(record) if query.matches(&record) => records.push(record),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Skipping malformed audit record in {:?}: {}", file, e),
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Parses a `--since`/`--until` bound: Unix seconds, a UTC date such as
+/// `2024-05-01` or `2024-05-01T18:00[:00]`, or an age such as `90m`, `12h`, `7d`.
+pub fn parse_time(value: &str, now_ms: u64) -> Result<u64> {
+    let invalid = || anyhow::anyhow!("Invalid time '{}': use Unix seconds, YYYY-MM-DD[THH:MM[:SS]] (UTC) or an age like 12h", value);
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return secs.checked_mul(1000).ok_or_else(invalid);
+    }
+
+    let units = [('s', 1), ('m', 60), ('h', 3600), ('d', 86400)];
+    if let Some((amount, unit_secs)) = units.iter().find_map(|(suffix, unit_secs)| {
+        value.strip_suffix(*suffix).and_then(|amount| amount.parse::<u64>().ok()).map(|amount| (amount, *unit_secs))
+    }) {
+        let age_ms = amount.checked_mul(unit_secs * 1000).ok_or_else(invalid)?;
+        return Ok(now_ms.saturating_sub(age_ms));
+    }
+
+    let (date, time) = value.split_once(['T', ' ']).unwrap_or((value, "00:00"));
+    let date: Vec<i64> = date.split('-').map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    let time: Vec<i64> = time.trim_end_matches('Z').split(':').map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    let (&[year, month, day], &[hour, minute, ref seconds @ ..]) = (date.as_slice(), time.as_slice()) else {
+        return Err(invalid());
+    };
+    let second = match seconds {
+        [] => 0,
+        [second] => *second,
+        _ => return Err(invalid()),
+    };
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return Err(invalid());
+    }
+
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(secs).ok().and_then(|secs| secs.checked_mul(1000)).ok_or_else(invalid)
+}
+
+/// `2024-05-01T18:00:00Z` for a Unix time in milliseconds.
+pub fn format_time(ms: u64) -> String {
+    let secs = (ms / 1000) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+// Proleptic Gregorian calendar conversions, from Howard Hinnant's date algorithms
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_log_rotates_and_queries_across_files() {
+        let dir = std::env::temp_dir().join(format!("vacuum-audit-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = AuditConfig {
+            path: Some(dir.join("audit.jsonl")),
+            max_size_kb: 1,
+            max_files: 2,
+            ..Default::default()
+        };
+
+        let log = AuditLog::new();
+        let caller = Caller { uid: 1000, pid: Some(42) };
+        for volume in 0..30 {
+            log.record(&config, AuditRecord::new(caller, &IpcCommand::SetVolume(volume), &IpcResponse::Success)).await;
+        }
+        log.record(&config, AuditRecord::new(caller, &IpcCommand::ToggleWifi, &IpcResponse::Error("nmcli failed".to_string()))).await;
+
+        assert!(rotated_path(&dir.join("audit.jsonl"), 2).exists());
+        assert!(!rotated_path(&dir.join("audit.jsonl"), 3).exists());
+
+        let all = query(&config, &AuditQuery::default()).unwrap();
+        assert!(all.len() < 31, "the oldest rotated file should have been dropped");
+        assert!(all.windows(2).all(|pair| pair[0].timestamp_ms <= pair[1].timestamp_ms));
+        assert_eq!(all.last().unwrap().args, serde_json::Value::Null);
+
+        let wifi = query(&config, &AuditQuery { command: Some("togglewifi".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(wifi.len(), 1);
+        assert_eq!(wifi[0].result, "error");
+        assert_eq!(wifi[0].detail.as_deref(), Some("nmcli failed"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_time_bounds() {
+        let now_ms = 1_714_586_400_000;
+        assert_eq!(parse_time("1714586400", now_ms).unwrap(), now_ms);
+        assert_eq!(parse_time("2024-05-01T18:00", now_ms).unwrap(), now_ms);
+        assert_eq!(parse_time("2024-05-01", now_ms).unwrap(), now_ms - 18 * 3_600_000);
+        assert_eq!(parse_time("2h", now_ms).unwrap(), now_ms - 2 * 3_600_000);
+        assert!(parse_time("2024-13-01", now_ms).is_err());
+        assert!(parse_time("yesterday", now_ms).is_err());
+        assert!(parse_time("99999999999999999", now_ms).is_err());
+        assert!(parse_time("99999999999999999d", now_ms).is_err());
+        assert!(parse_time("999999999999999-01-01", now_ms).is_err());
+        assert_eq!(format_time(now_ms), "2024-05-01T18:00:00Z");
+    }
+}
@@ -0,0 +1,170 @@
+use crate::config::SecurityConfig;
+use crate::daemon::IpcCommand;
+use anyhow::{Result, Context};
+use nix::unistd::{Gid, Group, Uid, User};
+use std::collections::HashSet;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+/// How a connected peer relates to the daemon's user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerRole {
+    Owner,
+    GroupMember,
+}
+
+/// Decides who may connect to the socket and which commands they may send.
+/// The daemon's own user may run everything; members of the optional
+/// `[security] allowed_group` may only run `group_commands`.
+#[derive(Debug, Clone)]
+pub struct PeerPolicy {
+    owner_uid: u32,
+    group_gid: Option<u32>,
+    group_members: Vec<String>,
+    group_commands: HashSet<String>,
+}
+
+impl PeerPolicy {
+    pub fn from_config(config: &SecurityConfig) -> Self {
+        let group = config.allowed_group.as_deref().and_then(|name| {
+            match Group::from_name(name) {
+                Ok(Some(group)) => Some(group),
+                Ok(None) => {
+                    tracing::warn!("Allowed group '{}' does not exist, only the owner may connect", name);
+                    None
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to look up allowed group '{}': {}", name, e);
+                    None
+                }
+            }
+        });
+
+        Self {
+            owner_uid: Uid::current().as_raw(),
+            group_gid: group.as_ref().map(|group| group.gid.as_raw()),
+            group_members: group.map(|group| group.mem).unwrap_or_default(),
+            group_commands: config.group_commands.iter().cloned().collect(),
+        }
+    }
+
+    pub fn group_gid(&self) -> Option<Gid> {
+        self.group_gid.map(Gid::from_raw)
+    }
+
+    /// Classifies a peer from its socket credentials, or `None` to reject it.
+    pub fn authorize(&self, uid: u32, gid: u32) -> Option<PeerRole> {
+        if uid == self.owner_uid {
+            return Some(PeerRole::Owner);
+        }
+
+        let group_gid = self.group_gid?;
+        if gid == group_gid {
+            return Some(PeerRole::GroupMember);
+        }
+
+        // Supplementary membership isn't part of SO_PEERCRED, so check the group entry
+        match User::from_uid(Uid::from_raw(uid)) {
+            Ok(Some(user)) if self.group_members.contains(&user.name) => Some(PeerRole::GroupMember),
+            _ => None,
+        }
+    }
+
+    pub fn allows(&self, role: PeerRole, command: &IpcCommand) -> bool {
+        match role {
+            PeerRole::Owner => true,
+            PeerRole::GroupMember => self.group_commands.contains(command.name()),
+        }
+    }
+}
+
+/// Creates the socket directory if needed and makes sure nobody but the owner
+/// (and the allowed group, for traversal only) can reach into it.
+pub fn prepare_runtime_dir(dir: &Path, group: Option<Gid>) -> Result<()> {
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create runtime directory {:?}", dir))?;
+
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect runtime directory {:?}", dir))?;
+    if !metadata.is_dir() || metadata.uid() != Uid::current().as_raw() {
+        return Err(anyhow::anyhow!(
+            "Runtime directory {:?} is not a directory owned by the current user", dir
+        ));
+    }
+
+    let mode = match group {
+        Some(gid) => {
+            nix::unistd::chown(dir, None, Some(gid))
+                .with_context(|| format!("Failed to hand runtime directory {:?} to the allowed group", dir))?;
+            0o710
+        }
+        None => 0o700,
+    };
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to restrict permissions on {:?}", dir))?;
+
+    Ok(())
+}
+
+/// Limits the bound socket to the owner, plus the allowed group if one is set.
+pub fn restrict_socket(socket_path: &Path, group: Option<Gid>) -> Result<()> {
+    let mode = match group {
+        Some(gid) => {
+            nix::unistd::chown(socket_path, None, Some(gid))
+                .with_context(|| format!("Failed to hand socket {:?} to the allowed group", socket_path))?;
+            0o660
+        }
+        None => 0o600,
+    };
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to restrict permissions on {:?}", socket_path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy_with_group(gid: Option<u32>) -> PeerPolicy {
+        PeerPolicy {
+            owner_uid: 1000,
+            group_gid: gid,
+            group_members: vec!["alice".to_string()],
+            group_commands: ["GetState".to_string()].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn test_only_owner_without_group() {
+        let policy = policy_with_group(None);
+
+        assert_eq!(policy.authorize(1000, 1000), Some(PeerRole::Owner));
+        assert_eq!(policy.authorize(1001, 1000), None);
+    }
+
+    #[test]
+    fn test_group_members_limited_to_group_commands() {
+        let policy = policy_with_group(Some(500));
+
+        assert_eq!(policy.authorize(1001, 500), Some(PeerRole::GroupMember));
+        assert!(policy.allows(PeerRole::GroupMember, &IpcCommand::GetState));
+        assert!(!policy.allows(PeerRole::GroupMember, &IpcCommand::Shutdown));
+        assert!(policy.allows(PeerRole::Owner, &IpcCommand::Shutdown));
+    }
+
+    #[test]
+    fn test_runtime_dir_is_private() {
+        let dir = std::env::temp_dir().join(format!("vacuum-auth-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        prepare_runtime_dir(&dir, None).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mode, 0o700);
+    }
+}
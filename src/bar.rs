@@ -0,0 +1,307 @@
+use crate::client::IpcClient;
+use crate::config::{BarConfig, load_config};
+use crate::daemon::IpcCommand;
+use crate::format::format_rate;
+use crate::instance;
+use crate::state::{StateSection, VacuumState};
+use anyhow::Result;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Blocks the bar can show, in their default order.
+pub const BLOCKS: [&str; 5] = ["network", "volume", "weather", "vpn", "media"];
+
+/// Built-in templates. Placeholders:
+/// network `{interface}` `{ip}` `{ssid}` `{rx}` `{tx}`, volume `{level}`,
+/// weather `{location}` `{temperature}` `{condition}`, vpn `{status}`,
+/// media `{artist}` `{title}` `{source}`.
+pub const TEMPLATES: [(&str, &str); 7] = [
+    ("network", "{interface} ↓{rx} ↑{tx}"),
+    ("volume", "VOL {level}%"),
+    ("volume_muted", "VOL muted"),
+    ("weather", "{temperature}°C {condition}"),
+    ("vpn", "VPN {status}"),
+    ("media", "{artist} - {title}"),
+    ("media_paused", ""),
+];
+
+/// Sections the blocks are built from.
+const SECTIONS: [StateSection; 6] = [
+    StateSection::NetworkStatus,
+    StateSection::NetworkTraffic,
+    StateSection::VolumeState,
+    StateSection::WeatherInfo,
+    StateSection::Toggles,
+    StateSection::AudioStatus,
+];
+
+/// How much a scroll on the volume block changes the volume.
+const VOLUME_STEP: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarFormat {
+    /// One `{"text": ...}` object per line for a waybar custom module
+    Waybar,
+    /// The i3bar protocol, with click events read from stdin
+    I3bar,
+    /// Plain lines with `%{A1:...:}` action tags for a polybar script module
+    Polybar,
+}
+
+impl BarFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "waybar" => Ok(Self::Waybar),
+            "i3bar" => Ok(Self::I3bar),
+            "polybar" => Ok(Self::Polybar),
+            other => Err(format!("Unknown bar format '{}', use waybar, i3bar or polybar", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub name: String,
+    pub text: String,
+}
+
+/// Renders the configured blocks, or only those in `only` if it isn't empty.
+/// Blocks whose template comes out empty are left out.
+pub fn render_blocks(config: &BarConfig, state: &VacuumState, only: &[String]) -> Vec<Block> {
+    config.blocks.iter()
+        .filter(|name| only.is_empty() || only.contains(name))
+        .filter_map(|name| {
+            let text = render_block(config, state, name);
+            (!text.is_empty()).then(|| Block { name: name.clone(), text })
+        })
+        .collect()
+}
+
+fn render_block(config: &BarConfig, state: &VacuumState, name: &str) -> String {
+    let (template, values): (&str, Vec<(&str, String)>) = match name {
+        "network" => (config.template("network"), vec![
+            ("interface", state.network_traffic.interface.clone()),
+            ("ip", state.network_status.ip_address.clone()),
+            ("ssid", state.network_status.ssid.clone().unwrap_or_default()),
+            ("rx", format_rate(state.network_traffic.rx_kbps)),
+            ("tx", format_rate(state.network_traffic.tx_kbps)),
+        ]),
+        "volume" => {
+            let template = if state.volume_state.muted { "volume_muted" } else { "volume" };
+            (config.template(template), vec![("level", state.volume_state.level_percent.to_string())])
+        }
+        "weather" => (config.template("weather"), vec![
+            ("location", state.weather_info.location_display.clone()),
+            ("temperature", state.weather_info.temperature_c.to_string()),
+            ("condition", state.weather_info.condition.clone()),
+        ]),
+        "vpn" => {
+            let status = if state.toggles.vpn_connected { "on" } else { "off" };
+            (config.template("vpn"), vec![("status", status.to_string())])
+        }
+        "media" => {
+            let template = if state.audio_status.playing { "media" } else { "media_paused" };
+            (config.template(template), vec![
+                ("artist", state.audio_status.artist.clone()),
+                ("title", state.audio_status.track_title.clone()),
+                ("source", state.audio_status.source_name.clone()),
+            ])
+        }
+        _ => return String::new(),
+    };
+
+    values.iter().fold(template.to_string(), |text, (key, value)| {
+        text.replace(&format!("{{{}}}", key), value)
+    })
+}
+
+/// The command a click on `block` sends. Buttons follow X11: 1 is the left
+/// button, 4 and 5 scroll up and down.
+pub fn click_command(block: &str, button: u8, state: &VacuumState) -> Option<IpcCommand> {
+    let level = state.volume_state.level_percent;
+    match (block, button) {
+        ("volume", 1) => Some(IpcCommand::ToggleMute),
+        ("volume", 4) => Some(IpcCommand::SetVolume(level.saturating_add(VOLUME_STEP).min(100))),
+        ("volume", 5) => Some(IpcCommand::SetVolume(level.saturating_sub(VOLUME_STEP))),
+        ("vpn", 1) => Some(IpcCommand::ToggleVpn),
+        ("network", 1) => Some(IpcCommand::ToggleWifi),
+        _ => None,
+    }
+}
+
+/// CLI arguments for a left click, for polybar, which runs a command per click.
+fn click_args(block: &str) -> Option<&'static str> {
+    match block {
+        "volume" => Some("volume mute"),
+        "vpn" => Some("vpn toggle"),
+        "network" => Some("wifi toggle"),
+        _ => None,
+    }
+}
+
+/// One output line in `format`. i3bar lines carry the trailing comma of the
+/// protocol's endless array.
+pub fn format_line(format: BarFormat, blocks: &[Block], separator: &str, exe: &str) -> String {
+    match format {
+        BarFormat::Waybar => {
+            // waybar parses both as Pango markup
+            let text: Vec<String> = blocks.iter().map(|block| escape_markup(&block.text)).collect();
+            let tooltip: Vec<String> = blocks.iter()
+                .map(|block| format!("{}: {}", block.name, escape_markup(&block.text)))
+                .collect();
+            serde_json::json!({
+                "text": text.join(separator),
+                "tooltip": tooltip.join("\n"),
+            })
+            .to_string()
+        }
+        BarFormat::I3bar => {
+            let blocks: Vec<_> = blocks.iter()
+                .map(|block| serde_json::json!({ "name": block.name, "full_text": block.text }))
+                .collect();
+            format!("{},", serde_json::Value::Array(blocks))
+        }
+        BarFormat::Polybar => {
+            let blocks: Vec<String> = blocks.iter()
+                .map(|block| {
+                    // Media titles and SSIDs come from outside; a literal `%{` in them
+                    // would otherwise be read as a formatting tag, even a click action
+                    let text = block.text.replace('%', "%%");
+                    match click_args(&block.name) {
+                        Some(args) => format!("%{{A1:{} {}:}}{}%{{A}}", exe.replace(':', "\\:"), args, text),
+                        None => text,
+                    }
+                })
+                .collect();
+            blocks.join(separator)
+        }
+    }
+}
+
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// A click reported by i3bar on stdin.
+#[derive(Debug, Deserialize)]
+struct ClickEvent {
+    name: String,
+    button: u8,
+}
+
+/// Prints a line whenever the bar's text changes, for as long as the daemon
+/// keeps the connection open.
+pub async fn run(client: IpcClient, format: BarFormat, only: Vec<String>) -> Result<()> {
+    let config = load_config().map(|config| config.bar).unwrap_or_default();
+    let mut exe = std::env::current_exe()
+        .map(|exe| exe.display().to_string())
+        .unwrap_or_else(|_| "vacuum-launcher".to_string());
+    // Clicks run the binary again, so they need to reach the same daemon
+    for arg in instance::current().args() {
+        exe.push(' ');
+        exe.push_str(&arg);
+    }
+
+    let client = Arc::new(client);
+    let mut updates = client.subscribe(SECTIONS.to_vec()).await?;
+    let state = Arc::new(Mutex::new(VacuumState::default()));
+
+    if format == BarFormat::I3bar {
+        println!(r#"{{"version":1,"click_events":true}}"#);
+        println!("[");
+        spawn_click_reader(client.clone(), state.clone());
+    }
+
+    let mut last_line = String::new();
+    while let Some(update) = updates.recv().await {
+        let line = {
+            let mut state = state.lock().unwrap();
+            state.apply(&update);
+            // A burst of updates, like the initial snapshot, gives one line
+            while let Ok(update) = updates.try_recv() {
+                state.apply(&update);
+            }
+            format_line(format, &render_blocks(&config, &state, &only), &config.separator, &exe)
+        };
+        if line != last_line {
+            println!("{}", line);
+            last_line = line;
+        }
+    }
+    Ok(())
+}
+
+fn spawn_click_reader(client: Arc<IpcClient>, state: Arc<Mutex<VacuumState>>) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            // Events arrive as elements of an endless JSON array
+            let line = line.trim().trim_start_matches(',');
+            let Ok(click) = serde_json::from_str::<ClickEvent>(line) else {
+                continue;
+            };
+            let command = click_command(&click.name, click.button, &state.lock().unwrap());
+            if let Some(command) = command
+                && let Err(e) = client.request(command).await
+            {
+                eprintln!("Failed to send click for {}: {:#}", click.name, e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_follow_templates_and_state() {
+        let mut config = BarConfig::default();
+        config.templates.insert("volume".to_string(), "{level}%".to_string());
+        let mut state = VacuumState::default();
+        state.volume_state.level_percent = 40;
+        state.network_traffic.rx_kbps = 1536.0;
+
+        let blocks = render_blocks(&config, &state, &[]);
+        let names: Vec<_> = blocks.iter().map(|block| block.name.as_str()).collect();
+        // Nothing is playing, so the media block is hidden
+        assert_eq!(names, ["network", "volume", "weather", "vpn"]);
+        assert_eq!(blocks[1].text, "40%");
+        assert!(blocks[0].text.contains("↓1.5 MiB/s"));
+
+        state.volume_state.muted = true;
+        let blocks = render_blocks(&config, &state, &["volume".to_string()]);
+        assert_eq!(blocks, [Block { name: "volume".to_string(), text: "VOL muted".to_string() }]);
+
+        let line = format_line(BarFormat::I3bar, &blocks, " | ", "/usr/bin/vacuum-launcher");
+        assert_eq!(line, r#"[{"full_text":"VOL muted","name":"volume"}],"#);
+        let line = format_line(BarFormat::Polybar, &blocks, " | ", "/usr/bin/vacuum-launcher");
+        assert_eq!(line, "%{A1:/usr/bin/vacuum-launcher volume mute:}VOL muted%{A}");
+    }
+
+    #[test]
+    fn test_hostile_text_is_escaped() {
+        let blocks = [Block {
+            name: "media".to_string(),
+            text: "%{A1:sh -c 'rm -rf ~':}x%{A} <b>&</b>".to_string(),
+        }];
+
+        let line = format_line(BarFormat::Polybar, &blocks, " | ", "/usr/bin/vacuum-launcher");
+        assert_eq!(line, "%%{A1:sh -c 'rm -rf ~':}x%%{A} <b>&</b>");
+
+        let line: serde_json::Value = serde_json::from_str(&format_line(BarFormat::Waybar, &blocks, " | ", "")).unwrap();
+        assert_eq!(line["text"], "%{A1:sh -c 'rm -rf ~':}x%{A} &lt;b&gt;&amp;&lt;/b&gt;");
+        assert_eq!(line["tooltip"], "media: %{A1:sh -c 'rm -rf ~':}x%{A} &lt;b&gt;&amp;&lt;/b&gt;");
+    }
+
+    #[test]
+    fn test_clicks_map_to_commands() {
+        let mut state = VacuumState::default();
+        state.volume_state.level_percent = 98;
+        assert_eq!(click_command("volume", 1, &state), Some(IpcCommand::ToggleMute));
+        assert_eq!(click_command("volume", 4, &state), Some(IpcCommand::SetVolume(100)));
+        assert_eq!(click_command("vpn", 1, &state), Some(IpcCommand::ToggleVpn));
+        assert_eq!(click_command("weather", 1, &state), None);
+    }
+}
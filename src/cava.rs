@@ -17,6 +17,13 @@ impl Default for AudioVisualizerData {
     }
 }
 
+/// Which visualizer implementation this binary was built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum VisualizerBackend {
+    Cava,
+    Stub,
+}
+
 // Conditional compilation for Cava integration
 #[cfg(cava_enabled)]
 mod cava_impl {
@@ -24,6 +31,8 @@ mod cava_impl {
     
     // Include the generated bindings
     include!(concat!(env!("OUT_DIR"), "/cava_bindings.rs"));
+
+    pub const BACKEND: VisualizerBackend = VisualizerBackend::Cava;
     
     pub struct AudioVisualizer {
         cava_plan: Option<*mut cava_plan>,
@@ -84,6 +93,8 @@ mod cava_impl {
 #[cfg(cava_disabled)]
 mod cava_impl {
     use super::*;
+
+    pub const BACKEND: VisualizerBackend = VisualizerBackend::Stub;
     
     pub struct AudioVisualizer {
         enabled: bool,
@@ -131,4 +142,4 @@ mod cava_impl {
 }
 
 // Re-export the implementation
-pub use cava_impl::AudioVisualizer;
\ No newline at end of file
+pub use cava_impl::{AudioVisualizer, BACKEND};
\ No newline at end of file
@@ -0,0 +1,463 @@
+use crate::bar::{self, BarFormat};
+use crate::client::IpcClient;
+use crate::daemon::{DaemonStatus, IpcCommand, IpcResponse};
+use crate::format::{self, OutputFormat};
+use crate::schedule::ScheduleTime;
+use crate::state::StateSection;
+use std::io::{IsTerminal, Write};
+
+/// Exit codes of the subcommands, so scripts and keybindings can tell
+/// failures apart.
+pub const EXIT_OK: i32 = 0;
+/// The daemon answered with `IpcResponse::Error`
+pub const EXIT_ERROR: i32 = 1;
+/// The command line could not be parsed
+pub const EXIT_USAGE: i32 = 2;
+/// The daemon is not running or the connection broke
+pub const EXIT_UNREACHABLE: i32 = 3;
+/// The action hit its timeout and was killed
+pub const EXIT_TIMED_OUT: i32 = 4;
+/// A power action is waiting for `confirm TOKEN`
+pub const EXIT_NEEDS_CONFIRMATION: i32 = 5;
+
+/// What a subcommand asks of the daemon.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Request(IpcCommand),
+    /// Logout, Reboot or Shutdown; `yes` confirms them without asking
+    Power { command: IpcCommand, yes: bool },
+    /// Prints every change to the given sections until the daemon goes away
+    Watch(Vec<StateSection>),
+    /// Prints the state, or the fields at `paths`, in `format`
+    State { paths: Vec<String>, format: OutputFormat },
+    /// Streams status-bar lines, restricted to `blocks` if any are given
+    Bar { format: BarFormat, blocks: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub action: Action,
+    /// Print the daemon's response as JSON instead of a human summary
+    pub json: bool,
+}
+
+pub fn print_usage() {
+    println!("Vacuum Launcher");
+    println!();
+    println!("USAGE:");
+    println!("    vacuum-launcher --daemon [--replace]");
+    println!("                                Start the background service; --replace stops a");
+    println!("                                running daemon first");
+    println!("    vacuum-launcher --toggle    Toggle the overlay display");
+    println!("    vacuum-launcher --get-state [PATH...] [--format json|toml|env|table]");
+    println!("                                Show current system state, or fields such as");
+    println!("                                system_info.cpu_load_percent");
+    println!("    vacuum-launcher --audit-log [--since TIME] [--until TIME] [--command NAME] [--json]");
+    println!("                                Show executed actions; TIME is Unix seconds,");
+    println!("                                YYYY-MM-DD[THH:MM] (UTC) or an age like 12h");
+    println!();
+    println!("GLOBAL OPTIONS (for the daemon and clients alike):");
+    println!("    --instance NAME             Run or reach a separate named daemon, with its own");
+    println!("                                runtime directory, config, audit log and bus name");
+    println!("    --socket PATH               Socket to serve or connect to (or VACUUM_SOCKET)");
+    println!("    --config PATH               Config file to load (or VACUUM_CONFIG)");
+    println!();
+    println!("COMMANDS (add --json for machine-readable output):");
+    println!("    volume set <0-100>          Set the default sink volume");
+    println!("    volume mute                 Toggle mute");
+    println!("    wifi|bluetooth|vpn toggle   Toggle a connection");
+    println!("    overlay toggle|show|hide    Change overlay visibility");
+    println!("    logout|reboot|shutdown [--yes]");
+    println!("                                Power actions; asks for confirmation unless --yes");
+    println!("    confirm|cancel <TOKEN>      Confirm or drop a pending power action");
+    println!("    schedule shutdown|reboot --at <TIME>|--in <DURATION>");
+    println!("                                Run a power action later; TIME is anything `date -d`");
+    println!("                                understands (e.g. 18:00), DURATION e.g. 90, 30m, 2h");
+    println!("    schedule cancel             Drop the scheduled power action");
+    println!("    launch app                  Open the application launcher");
+    println!("    launch url <URL>            Open a URL in the configured browser");
+    println!("    visualizer                  Show the audio visualizer bars");
+    println!("    state [PATH...] [--format F]  Same as --get-state");
+    println!("    health                      Show how collection is doing");
+    println!("    status                      Show version, PID, uptime, paths, loops and clients");
+    println!("    config show|reload          Show (secrets redacted) or reload config.toml");
+    println!("    config patch <JSON>         Apply a JSON merge patch to the config");
+    println!("    watch [SECTION...]          Print state changes as JSON lines");
+    println!("    bar --format waybar|i3bar|polybar [--block NAME]...");
+    println!("                                Print status-bar lines; blocks are network, volume,");
+    println!("                                weather, vpn and media");
+    println!();
+    println!("EXIT CODES:");
+    println!("    0 success, 1 daemon error, 2 usage error, 3 daemon unreachable,");
+    println!("    4 action timed out, 5 confirmation required");
+    println!();
+    println!("The daemon must be running before using --toggle, unless [ipc] autostart is set.");
+    println!("Configure hotkey (default Super+Shift+Space) to run --toggle.");
+}
+
+pub fn parse(args: &[String]) -> Result<Invocation, String> {
+    let mut json = false;
+    let mut yes = false;
+    let mut format = None;
+    let mut blocks = Vec::new();
+    let mut words = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--yes" | "-y" => yes = true,
+            "--format" => format = Some(args.next().ok_or("--format needs a value")?.as_str()),
+            "--block" => blocks.push(args.next().ok_or("--block needs a value")?.clone()),
+            word => words.push(word),
+        }
+    }
+
+    if let ["bar"] = words.as_slice() {
+        let format = BarFormat::parse(format.ok_or("bar needs --format waybar, i3bar or polybar")?)?;
+        if let Some(block) = blocks.iter().find(|block| !bar::BLOCKS.contains(&block.as_str())) {
+            return Err(format!("Unknown bar block '{}'", block));
+        }
+        return Ok(Invocation { action: Action::Bar { format, blocks }, json });
+    }
+    if !blocks.is_empty() {
+        return Err("--block only applies to bar".to_string());
+    }
+    if let ["state", paths @ ..] = words.as_slice() {
+        let format = format.map(OutputFormat::parse).transpose()?.unwrap_or(OutputFormat::Json);
+        if json && format != OutputFormat::Json {
+            return Err("--json conflicts with --format".to_string());
+        }
+        let paths = paths.iter().map(|path| path.to_string()).collect();
+        return Ok(Invocation { action: Action::State { paths, format }, json });
+    }
+    if format.is_some() {
+        return Err("--format only applies to state and bar".to_string());
+    }
+
+    let command = match words.as_slice() {
+        ["volume", "set", level] => {
+            let level = level.trim_end_matches('%').parse::<u8>().ok().filter(|level| *level <= 100)
+                .ok_or_else(|| format!("Volume must be between 0 and 100, got '{}'", level))?;
+            IpcCommand::SetVolume(level)
+        }
+        ["volume", "mute"] => IpcCommand::ToggleMute,
+        ["wifi", "toggle"] => IpcCommand::ToggleWifi,
+        ["bluetooth", "toggle"] => IpcCommand::ToggleBluetooth,
+        ["vpn", "toggle"] => IpcCommand::ToggleVpn,
+        ["overlay", "toggle"] => IpcCommand::ToggleOverlay,
+        ["overlay", "show"] => IpcCommand::ShowOverlay,
+        ["overlay", "hide"] => IpcCommand::HideOverlay,
+        [power @ ("logout" | "reboot" | "shutdown")] => {
+            let command = match *power {
+                "logout" => IpcCommand::Logout,
+                "reboot" => IpcCommand::Reboot,
+                _ => IpcCommand::Shutdown,
+            };
+            return Ok(Invocation { action: Action::Power { command, yes }, json });
+        }
+        ["confirm", token] => IpcCommand::ConfirmAction(token.to_string()),
+        ["cancel", token] => IpcCommand::CancelAction(token.to_string()),
+        ["schedule", "cancel"] => IpcCommand::CancelScheduled,
+        ["schedule", action @ ("shutdown" | "reboot"), option, value] => {
+            let when = match *option {
+                "--at" => ScheduleTime::At(resolve_time(value)?),
+                "--in" => ScheduleTime::AfterSecs(parse_duration(value)?),
+                other => return Err(format!("Unknown schedule option '{}', use --at or --in", other)),
+            };
+            match *action {
+                "shutdown" => IpcCommand::ScheduleShutdown(when),
+                _ => IpcCommand::ScheduleReboot(when),
+            }
+        }
+        ["launch", "app"] => IpcCommand::LaunchApp,
+        ["launch", "url", url] => IpcCommand::LaunchUrl(url.to_string()),
+        ["visualizer"] => IpcCommand::GetAudioVisualizer,
+        ["health"] => IpcCommand::GetHealth,
+        ["status"] => IpcCommand::Status,
+        ["config", "show"] => IpcCommand::GetConfig,
+        ["config", "reload"] => IpcCommand::ReloadConfig,
+        ["config", "patch", patch] => {
+            let patch = serde_json::from_str(patch).map_err(|e| format!("Config patch is not valid JSON: {}", e))?;
+            IpcCommand::PatchConfig(patch)
+        }
+        ["watch", sections @ ..] => {
+            let sections = sections.iter()
+                .map(|name| StateSection::from_name(name).ok_or_else(|| format!("Unknown state section '{}'", name)))
+                .collect::<Result<_, _>>()?;
+            return Ok(Invocation { action: Action::Watch(sections), json });
+        }
+        [] => return Err("No command given".to_string()),
+        _ => return Err(format!("Unknown command '{}'", words.join(" "))),
+    };
+
+    if yes {
+        return Err("--yes only applies to logout, reboot and shutdown".to_string());
+    }
+    Ok(Invocation { action: Action::Request(command), json })
+}
+
+/// `90`, `30s`, `15m`, `2h` or `1d` as seconds.
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let (amount, unit_secs) = match value.char_indices().last() {
+        Some((index, 's')) => (&value[..index], 1),
+        Some((index, 'm')) => (&value[..index], 60),
+        Some((index, 'h')) => (&value[..index], 3600),
+        Some((index, 'd')) => (&value[..index], 86400),
+        _ => (value, 1),
+    };
+    amount.parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(unit_secs))
+        .ok_or_else(|| format!("Invalid duration '{}', use e.g. 90, 30m or 2h", value))
+}
+
+/// Unix seconds for a wall-clock time in the local timezone, via `date -d`.
+fn resolve_time(value: &str) -> Result<u64, String> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(secs);
+    }
+    let output = std::process::Command::new("date")
+        .args(["-d", value, "+%s"])
+        .output()
+        .map_err(|e| format!("Failed to run date: {}", e))?;
+    String::from_utf8_lossy(&output.stdout).trim().parse::<u64>()
+        .ok()
+        .filter(|_| output.status.success())
+        .ok_or_else(|| format!("Invalid time '{}'", value))
+}
+
+pub fn exit_code(response: &IpcResponse) -> i32 {
+    match response {
+        IpcResponse::Error(_) => EXIT_ERROR,
+        IpcResponse::ActionTimedOut { .. } => EXIT_TIMED_OUT,
+        IpcResponse::ConfirmationRequired { .. } => EXIT_NEEDS_CONFIRMATION,
+        _ => EXIT_OK,
+    }
+}
+
+/// Runs a subcommand against the daemon and returns the process exit code.
+pub async fn run(args: &[String]) -> i32 {
+    let invocation = match parse(args) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Run `vacuum-launcher --help` for usage.");
+            return EXIT_USAGE;
+        }
+    };
+
+    let client = match IpcClient::connect().await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to reach the daemon: {:#}", e);
+            return EXIT_UNREACHABLE;
+        }
+    };
+
+    let json = invocation.json;
+    let result = match invocation.action {
+        Action::Request(command) => client.request(command.clone()).await
+            .map(|response| report(&command, &response, json)),
+        Action::Power { command, yes } => power(&client, command, yes, json).await,
+        Action::Watch(sections) => watch(&client, sections).await,
+        Action::State { paths, format } => show_state(&client, &paths, format).await,
+        Action::Bar { format, blocks } => bar::run(client, format, blocks).await.map(|()| {
+            eprintln!("The daemon closed the connection");
+            EXIT_UNREACHABLE
+        }),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Lost connection to the daemon: {:#}", e);
+        EXIT_UNREACHABLE
+    })
+}
+
+/// Sends a power command and, when the daemon wants confirmation, confirms
+/// it for `--yes`, asks on a terminal, or leaves the token to the caller.
+async fn power(client: &IpcClient, command: IpcCommand, yes: bool, json: bool) -> anyhow::Result<i32> {
+    let response = client.request(command.clone()).await?;
+    let IpcResponse::ConfirmationRequired { token, .. } = &response else {
+        return Ok(report(&command, &response, json));
+    };
+
+    let confirmed = if yes {
+        true
+    } else if !json && std::io::stdin().is_terminal() {
+        ask(&format!("{} now? [y/N] ", command.name()))
+    } else {
+        return Ok(report(&command, &response, json));
+    };
+
+    if !confirmed {
+        client.request(IpcCommand::CancelAction(token.clone())).await?;
+        eprintln!("Cancelled");
+        return Ok(EXIT_ERROR);
+    }
+    let response = client.request(IpcCommand::ConfirmAction(token.clone())).await?;
+    Ok(report(&command, &response, json))
+}
+
+fn ask(prompt: &str) -> bool {
+    print!("{}", prompt);
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+async fn show_state(client: &IpcClient, paths: &[String], format: OutputFormat) -> anyhow::Result<i32> {
+    let state = match client.request(IpcCommand::GetState).await? {
+        IpcResponse::State(state) => serde_json::to_value(state)?,
+        response => return Ok(report(&IpcCommand::GetState, &response, false)),
+    };
+
+    let root = match paths {
+        [path] => Some(path.as_str()),
+        _ => None,
+    };
+    match format::select(&state, paths).and_then(|selection| format::render(&selection, root, format)) {
+        Ok(text) => {
+            print!("{}", text);
+            if !text.ends_with('\n') {
+                println!();
+            }
+            Ok(EXIT_OK)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            Ok(EXIT_USAGE)
+        }
+    }
+}
+
+async fn watch(client: &IpcClient, sections: Vec<StateSection>) -> anyhow::Result<i32> {
+    let mut updates = client.subscribe(sections).await?;
+    while let Some(update) = updates.recv().await {
+        println!("{}", serde_json::to_string(&update)?);
+    }
+    eprintln!("The daemon closed the connection");
+    Ok(EXIT_UNREACHABLE)
+}
+
+/// Prints a response for `command` and returns the matching exit code.
+fn report(command: &IpcCommand, response: &IpcResponse, json: bool) -> i32 {
+    if json {
+        match serde_json::to_string(response) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("Failed to serialize response: {}", e),
+        }
+        return exit_code(response);
+    }
+
+    match response {
+        IpcResponse::Success => {}
+        IpcResponse::ToggleResult(on) => println!("{}", toggle_message(command, *on)),
+        IpcResponse::State(state) => print_pretty(state),
+        IpcResponse::AudioVisualizer(data) => print_pretty(data),
+        IpcResponse::StateChanged(update) => print_pretty(update),
+        IpcResponse::Hello(info) => print_pretty(info),
+        IpcResponse::Config(config) => print_pretty(config),
+        IpcResponse::Health(health) => print_pretty(health),
+        IpcResponse::Status(status) => print_status(status),
+        IpcResponse::Error(e) => eprintln!("Error: {}", e),
+        IpcResponse::ActionTimedOut { command, timeout_ms } => {
+            eprintln!("{} timed out after {}s and was stopped", command, *timeout_ms as f64 / 1000.0);
+        }
+        IpcResponse::ConfirmationRequired { token, command, expires_in_ms } => {
+            println!(
+                "{} needs confirmation: run `vacuum-launcher confirm {}` within {}s",
+                command, token, expires_in_ms / 1000
+            );
+        }
+    }
+    exit_code(response)
+}
+
+fn toggle_message(command: &IpcCommand, on: bool) -> String {
+    let (subject, on_text, off_text) = match command {
+        IpcCommand::ToggleWifi => ("Wi-Fi", "on", "off"),
+        IpcCommand::ToggleBluetooth => ("Bluetooth", "on", "off"),
+        IpcCommand::ToggleVpn => ("VPN", "connected", "disconnected"),
+        IpcCommand::ToggleMute => ("Sound", "muted", "unmuted"),
+        _ => ("Overlay", "shown", "hidden"),
+    };
+    format!("{} {}", subject, if on { on_text } else { off_text })
+}
+
+fn print_status(status: &DaemonStatus) {
+    println!("vacuum-launcher {} (pid {}), up {}", status.daemon_version, status.pid, format::format_duration(status.uptime_secs));
+    println!("socket   {}", status.socket_path.display());
+    println!("config   {}", status.config_path.display());
+    println!("clients  {}", status.clients);
+    println!("cava     {}", status.cava);
+    println!("loops:");
+    for health in &status.loops {
+        let last_run = match (health.last_duration_ms, health.last_tick_ms_ago) {
+            (Some(duration_ms), Some(ago_ms)) => {
+                format!("last run took {} ms, {} ago", duration_ms, format::format_duration(ago_ms / 1000))
+            }
+            _ => "not run yet".to_string(),
+        };
+        let stalled = if health.stalled { " (stalled)" } else { "" };
+        println!(
+            "  {:8} every {}, {}{}",
+            health.name, format::format_duration(health.interval_secs), last_run, stalled
+        );
+    }
+}
+
+fn print_pretty<T: serde::Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Failed to serialize response: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_words(line: &str) -> Result<Invocation, String> {
+        parse(&line.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        assert_eq!(
+            parse_words("volume set 40 --json").unwrap(),
+            Invocation { action: Action::Request(IpcCommand::SetVolume(40)), json: true }
+        );
+        assert_eq!(parse_words("wifi toggle").unwrap().action, Action::Request(IpcCommand::ToggleWifi));
+        assert_eq!(
+            parse_words("reboot --yes").unwrap().action,
+            Action::Power { command: IpcCommand::Reboot, yes: true }
+        );
+        assert_eq!(
+            parse_words("schedule reboot --in 30m").unwrap().action,
+            Action::Request(IpcCommand::ScheduleReboot(ScheduleTime::AfterSecs(1800)))
+        );
+        assert!(parse_words("schedule reboot --in 999999999999999999d").is_err());
+        assert_eq!(
+            parse_words("watch system_info Toggles").unwrap().action,
+            Action::Watch(vec![StateSection::SystemInfo, StateSection::Toggles])
+        );
+
+        assert!(parse_words("volume set 140").is_err());
+        assert!(parse_words("wifi toggle --yes").is_err());
+        assert!(parse_words("wifi on").is_err());
+        assert!(parse_words("watch nonsense").is_err());
+
+        assert_eq!(
+            parse_words("state system_info.cpu_load_percent --format env").unwrap().action,
+            Action::State { paths: vec!["system_info.cpu_load_percent".to_string()], format: OutputFormat::Env }
+        );
+        assert!(parse_words("state --format yaml").is_err());
+        assert!(parse_words("wifi toggle --format table").is_err());
+
+        assert_eq!(
+            parse_words("bar --format i3bar --block volume").unwrap().action,
+            Action::Bar { format: BarFormat::I3bar, blocks: vec!["volume".to_string()] }
+        );
+        assert!(parse_words("bar").is_err());
+        assert!(parse_words("bar --format waybar --block cpu").is_err());
+    }
+}
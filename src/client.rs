@@ -0,0 +1,406 @@
+use crate::config::{IpcConfig, get_config_path, load_config_from};
+use crate::daemon::{DaemonInfo, IpcCommand, IpcReply, IpcRequest, IpcResponse, get_socket_path};
+use crate::instance;
+use crate::state::{StateSection, StateUpdate};
+use crate::ipc;
+use anyhow::{Result, Context};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UnixStream;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::{Mutex as AsyncMutex, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+type PendingReplies = Arc<Mutex<HashMap<u64, oneshot::Sender<IpcResponse>>>>;
+type Subscriptions = Arc<Mutex<HashMap<u64, Subscription>>>;
+type Writer = Arc<AsyncMutex<Connection>>;
+
+/// The wait between connection attempts starts here and doubles up to `MAX_BACKOFF`.
+const FIRST_BACKOFF: Duration = Duration::from_millis(50);
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// The write side of the client's connection to the daemon.
+enum Connection {
+    Open(OwnedWriteHalf),
+    /// Lost, and being reopened for the client's subscriptions
+    Reconnecting,
+    /// Lost with nothing to resume; a client made with `connect_to` reopens
+    /// it for the next request
+    Closed,
+}
+
+/// A stream of updates, reopened under the same ID after a reconnect.
+struct Subscription {
+    command: IpcCommand,
+    updates: mpsc::UnboundedSender<StateUpdate>,
+}
+
+/// How a client reaches the daemon.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub max_message_bytes: usize,
+    /// Start `vacuum-launcher --daemon` if nothing is listening
+    pub autostart: bool,
+    /// How long to keep retrying while the daemon comes up
+    pub retry_for: Duration,
+}
+
+impl ConnectOptions {
+    pub fn from_config(config: &IpcConfig) -> Self {
+        Self {
+            max_message_bytes: config.max_message_bytes,
+            autostart: config.autostart,
+            retry_for: Duration::from_secs(config.connect_timeout_secs),
+        }
+    }
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self::from_config(&IpcConfig::default())
+    }
+}
+
+/// Where a client connected with `connect_to` reconnects after the daemon restarts.
+struct Reconnect {
+    socket_path: PathBuf,
+    options: ConnectOptions,
+}
+
+/// Long-lived connection to the daemon. Requests may be issued concurrently
+/// from several tasks; each one is matched to its reply by request ID.
+pub struct IpcClient {
+    writer: Writer,
+    pending: PendingReplies,
+    subscriptions: Subscriptions,
+    next_id: AtomicU64,
+    max_len: usize,
+    reconnect: Option<Arc<Reconnect>>,
+    reader_task: Mutex<JoinHandle<()>>,
+}
+
+impl IpcClient {
+    /// Connects to this instance's daemon with the `[ipc]` client settings.
+    pub async fn connect() -> Result<Self> {
+        // Connecting shouldn't create a config file as a side effect
+        let options = match get_config_path() {
+            Ok(path) if path.exists() => load_config_from(&path)
+                .map(|config| ConnectOptions::from_config(&config.ipc))
+                .unwrap_or_default(),
+            _ => ConnectOptions::default(),
+        };
+        Self::connect_to(&get_socket_path(), options).await
+    }
+
+    /// Connects to the daemon at `socket_path`, waiting for it to come up. If
+    /// the connection drops later, the client reconnects and reopens its
+    /// subscriptions for as long as anyone is still receiving them.
+    pub async fn connect_to(socket_path: &Path, options: ConnectOptions) -> Result<Self> {
+        let stream = open(socket_path, &options).await?;
+        let max_len = options.max_message_bytes;
+        let reconnect = Reconnect { socket_path: socket_path.to_path_buf(), options };
+        Ok(Self::start(stream, max_len, Some(Arc::new(reconnect))))
+    }
+
+    /// Wraps an already connected stream; the client ends with it.
+    pub fn from_stream(stream: UnixStream, max_len: usize) -> Self {
+        Self::start(stream, max_len, None)
+    }
+
+    fn start(stream: UnixStream, max_len: usize, reconnect: Option<Arc<Reconnect>>) -> Self {
+        let (reader, writer) = stream.into_split();
+        let writer: Writer = Arc::new(AsyncMutex::new(Connection::Open(writer)));
+        let pending: PendingReplies = Arc::new(Mutex::new(HashMap::new()));
+        let subscriptions: Subscriptions = Arc::new(Mutex::new(HashMap::new()));
+        let reader_task = spawn_reader(reader, max_len, writer.clone(), pending.clone(), subscriptions.clone(), reconnect.clone());
+
+        Self {
+            writer,
+            pending,
+            subscriptions,
+            next_id: AtomicU64::new(1),
+            max_len,
+            reconnect,
+            reader_task: Mutex::new(reader_task),
+        }
+    }
+
+    pub async fn request(&self, command: IpcCommand) -> Result<IpcResponse> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.send_request(id, command).await
+    }
+
+    /// Introduces this client and returns the daemon's version and capabilities.
+    pub async fn hello(&self, client_name: &str) -> Result<DaemonInfo> {
+        let command = IpcCommand::Hello {
+            protocol_version: ipc::PROTOCOL_VERSION,
+            client_name: client_name.to_string(),
+        };
+        match self.request(command).await? {
+            IpcResponse::Hello(info) => Ok(info),
+            IpcResponse::Error(e) => Err(anyhow::anyhow!("Daemon rejected handshake: {}", e)),
+            other => Err(anyhow::anyhow!("Unexpected handshake response: {:?}", other)),
+        }
+    }
+
+    /// Subscribes to state changes. The receiver first yields the current value
+    /// of each section, then every change, until the connection closes.
+    pub async fn subscribe(&self, sections: Vec<StateSection>) -> Result<mpsc::UnboundedReceiver<StateUpdate>> {
+        self.open_stream(IpcCommand::Subscribe { sections }).await
+    }
+
+    /// Sends a command whose acknowledgement is followed by pushed state updates.
+    async fn open_stream(&self, command: IpcCommand) -> Result<mpsc::UnboundedReceiver<StateUpdate>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (updates_tx, updates_rx) = mpsc::unbounded_channel();
+        let subscription = Subscription { command: command.clone(), updates: updates_tx };
+        self.subscriptions.lock().unwrap().insert(id, subscription);
+
+        match self.send_request(id, command).await {
+            Ok(IpcResponse::Success) => Ok(updates_rx),
+            Ok(IpcResponse::Error(e)) => {
+                self.subscriptions.lock().unwrap().remove(&id);
+                Err(anyhow::anyhow!("Daemon rejected subscription: {}", e))
+            }
+            Ok(other) => {
+                self.subscriptions.lock().unwrap().remove(&id);
+                Err(anyhow::anyhow!("Unexpected subscription response: {:?}", other))
+            }
+            Err(e) => {
+                self.subscriptions.lock().unwrap().remove(&id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Registers as an overlay frontend. The receiver yields `Overlay` state
+    /// updates, starting with the current one, until the connection closes.
+    pub async fn register_overlay(&self, name: &str) -> Result<mpsc::UnboundedReceiver<StateUpdate>> {
+        let command = IpcCommand::RegisterOverlay { name: name.to_string() };
+        self.open_stream(command).await
+    }
+
+    async fn send_request(&self, id: u64, command: IpcCommand) -> Result<IpcResponse> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, reply_tx);
+
+        let request = IpcRequest { id, command };
+        let sent = {
+            let mut connection = self.writer.lock().await;
+            if let (Connection::Closed, Some(reconnect)) = (&*connection, &self.reconnect) {
+                // Nothing was left to resume, so reconnect now that someone needs it
+                match open(&reconnect.socket_path, &reconnect.options).await {
+                    Ok(stream) => {
+                        tracing::debug!("Reconnected to the daemon");
+                        let (reader, writer) = stream.into_split();
+                        *connection = Connection::Open(writer);
+                        let reader_task = spawn_reader(
+                            reader, self.max_len, self.writer.clone(), self.pending.clone(),
+                            self.subscriptions.clone(), self.reconnect.clone(),
+                        );
+                        *self.reader_task.lock().unwrap() = reader_task;
+                    }
+                    Err(e) => {
+                        self.pending.lock().unwrap().remove(&id);
+                        return Err(e);
+                    }
+                }
+            }
+            match &mut *connection {
+                Connection::Open(writer) => ipc::write_message(writer, &request, self.max_len).await,
+                Connection::Reconnecting => Err(anyhow::anyhow!("Reconnecting to the daemon")),
+                Connection::Closed => Err(anyhow::anyhow!("Connection to the daemon is closed")),
+            }
+        };
+        if let Err(e) = sent {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        reply_rx
+            .await
+            .map_err(|_| anyhow::anyhow!("No response from daemon"))
+    }
+}
+
+impl Drop for IpcClient {
+    fn drop(&mut self) {
+        self.reader_task.lock().unwrap().abort();
+    }
+}
+
+/// Routes replies from `reader` and, once the connection drops, resumes
+/// any subscriptions on a new one.
+fn spawn_reader(
+    reader: OwnedReadHalf,
+    max_len: usize,
+    writer: Writer,
+    pending: PendingReplies,
+    subscriptions: Subscriptions,
+    reconnect: Option<Arc<Reconnect>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut reader = reader;
+        loop {
+            route_replies(&mut reader, max_len, &pending, &subscriptions).await;
+
+            // Stop writing first, so no request is left waiting on the dead connection
+            *writer.lock().await = match reconnect {
+                Some(_) => Connection::Reconnecting,
+                None => Connection::Closed,
+            };
+            // Dropping the senders wakes every outstanding request with an error
+            pending.lock().unwrap().clear();
+
+            let Some(reconnect) = &reconnect else {
+                break;
+            };
+            match reconnect.resume(&subscriptions, &writer).await {
+                Some(resumed) => reader = resumed,
+                None => break,
+            }
+        }
+        subscriptions.lock().unwrap().clear();
+    })
+}
+
+/// Hands each reply to its request or subscription until the connection closes.
+async fn route_replies(reader: &mut OwnedReadHalf, max_len: usize, pending: &PendingReplies, subscriptions: &Subscriptions) {
+    loop {
+        let reply = match ipc::read_message::<_, IpcReply>(reader, max_len).await {
+            Ok(Some(reply)) => reply,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("IPC connection to daemon failed: {}", e);
+                return;
+            }
+        };
+
+        // The first reply to a request completes it; later replies
+        // with the same ID are pushed subscription updates
+        let waiter = pending.lock().unwrap().remove(&reply.id);
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(reply.response);
+            continue;
+        }
+
+        let mut subscriptions = subscriptions.lock().unwrap();
+        match (subscriptions.get(&reply.id), reply.response) {
+            (Some(subscription), IpcResponse::StateChanged(update)) => {
+                if subscription.updates.send(update).is_err() {
+                    subscriptions.remove(&reply.id);
+                }
+            }
+            // Acknowledges a subscription reopened after a reconnect
+            (Some(_), IpcResponse::Success) => {}
+            (Some(_), IpcResponse::Error(e)) => {
+                tracing::warn!("Daemon refused to reopen subscription {}: {}", reply.id, e);
+                subscriptions.remove(&reply.id);
+            }
+            (_, response) => {
+                tracing::warn!("Dropping IPC reply for unknown request {}: {:?}", reply.id, response);
+            }
+        }
+    }
+}
+
+impl Reconnect {
+    /// Reconnects and reopens every subscription that still has a receiver.
+    /// Keeps trying until it succeeds, or returns `None` once nobody listens.
+    async fn resume(&self, subscriptions: &Subscriptions, writer: &Writer) -> Option<OwnedReadHalf> {
+        loop {
+            let streams: Vec<(u64, IpcCommand)> = {
+                // Giving up under the writer lets the next request reconnect instead
+                let mut writer = writer.lock().await;
+                let mut subscriptions = subscriptions.lock().unwrap();
+                subscriptions.retain(|_, subscription| !subscription.updates.is_closed());
+                if subscriptions.is_empty() {
+                    *writer = Connection::Closed;
+                    return None;
+                }
+                subscriptions.iter().map(|(id, subscription)| (*id, subscription.command.clone())).collect()
+            };
+
+            let stream = match open(&self.socket_path, &self.options).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("Daemon still unreachable: {:#}", e);
+                    tokio::time::sleep(MAX_BACKOFF).await;
+                    continue;
+                }
+            };
+
+            // Hold the writer so no new request overtakes the reopened streams
+            let (reader, mut resumed) = stream.into_split();
+            let mut writer = writer.lock().await;
+            let mut reopened = true;
+            for (id, command) in streams {
+                let request = IpcRequest { id, command };
+                if let Err(e) = ipc::write_message(&mut resumed, &request, self.options.max_message_bytes).await {
+                    tracing::debug!("Failed to reopen subscription {}: {:#}", id, e);
+                    reopened = false;
+                    break;
+                }
+            }
+            if reopened {
+                tracing::debug!("Reconnected to the daemon");
+                *writer = Connection::Open(resumed);
+                return Some(reader);
+            }
+        }
+    }
+}
+
+/// Connects to `socket_path`, starting the daemon first if allowed, and
+/// retries with exponential backoff until `retry_for` runs out. Only a
+/// missing socket or a refused connection is worth retrying.
+async fn open(socket_path: &Path, options: &ConnectOptions) -> Result<UnixStream> {
+    let deadline = Instant::now() + options.retry_for;
+    let mut backoff = FIRST_BACKOFF;
+    let mut started = false;
+    loop {
+        let error = match UnixStream::connect(socket_path).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => e,
+        };
+        let retry = matches!(error.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused);
+        if retry && options.autostart && !started {
+            started = true;
+            if let Err(e) = start_daemon() {
+                tracing::warn!("Failed to start the daemon: {:#}", e);
+            }
+        }
+
+        let now = Instant::now();
+        if !retry || now >= deadline {
+            return Err(error).context("Failed to connect to daemon");
+        }
+        tokio::time::sleep(backoff.min(deadline - now)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Runs `vacuum-launcher --daemon` for this instance in its own process
+/// group, so it outlives the client and ignores the terminal's Ctrl-C. If two
+/// clients race, the PID file lock turns the second daemon away.
+fn start_daemon() -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to find the vacuum-launcher binary")?;
+    tracing::debug!("No daemon running, starting one");
+    // Tokio reaps the child once it exits, even after the handle is dropped
+    tokio::process::Command::new(exe)
+        .arg("--daemon")
+        .args(instance::current().args())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Failed to spawn the daemon")?;
+    Ok(())
+}
@@ -17,6 +17,12 @@ pub struct SystemCollector {
     audio_visualizer: AudioVisualizer,
 }
 
+impl Default for SystemCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SystemCollector {
     pub fn new() -> Self {
         let mut audio_visualizer = AudioVisualizer::new(32); // 32 frequency bands
@@ -138,7 +144,7 @@ impl SystemCollector {
     pub fn collect_storage_info(&mut self) -> Result<Vec<DiskInfo>> {
         // Use df command instead of sysinfo for disk info
         let output = Command::new("df")
-            .args(&["-h", "--output=source,size,used,pcent,target"])
+            .args(["-h", "--output=source,size,used,pcent,target"])
             .output()
             .context("Failed to get disk information")?;
 
@@ -180,14 +186,14 @@ impl SystemCollector {
             return None;
         }
 
-        let (num_str, unit) = if size_str.ends_with('K') {
-            (&size_str[..size_str.len()-1], 1024u64)
-        } else if size_str.ends_with('M') {
-            (&size_str[..size_str.len()-1], 1024u64 * 1024)
-        } else if size_str.ends_with('G') {
-            (&size_str[..size_str.len()-1], 1024u64 * 1024 * 1024)
-        } else if size_str.ends_with('T') {
-            (&size_str[..size_str.len()-1], 1024u64 * 1024 * 1024 * 1024)
+        let (num_str, unit) = if let Some(num) = size_str.strip_suffix('K') {
+            (num, 1024u64)
+        } else if let Some(num) = size_str.strip_suffix('M') {
+            (num, 1024u64 * 1024)
+        } else if let Some(num) = size_str.strip_suffix('G') {
+            (num, 1024u64 * 1024 * 1024)
+        } else if let Some(num) = size_str.strip_suffix('T') {
+            (num, 1024u64 * 1024 * 1024 * 1024)
         } else {
             (size_str, 1u64)
         };
@@ -197,7 +203,7 @@ impl SystemCollector {
 
     pub fn collect_network_status(&self) -> Result<NetworkStatus> {
         let output = Command::new("ip")
-            .args(&["route", "get", "8.8.8.8"])
+            .args(["route", "get", "8.8.8.8"])
             .output()
             .context("Failed to get route information")?;
 
@@ -238,7 +244,7 @@ impl SystemCollector {
 
     fn get_wifi_ssid(&self, interface: &str) -> Result<String> {
         let output = Command::new("iwgetid")
-            .args(&["-r", interface])
+            .args(["-r", interface])
             .output()
             .context("Failed to get WiFi SSID")?;
 
@@ -286,7 +292,7 @@ impl SystemCollector {
     pub fn collect_audio_status(&self) -> Result<AudioStatus> {
         // Try to get MPRIS info
         let output = Command::new("playerctl")
-            .args(&["metadata", "--format", "{{ playerName }}|{{ title }}|{{ artist }}|{{ status }}"])
+            .args(["metadata", "--format", "{{ playerName }}|{{ title }}|{{ artist }}|{{ status }}"])
             .output();
 
         if let Ok(output) = output {
@@ -309,7 +315,7 @@ impl SystemCollector {
 
     pub fn collect_volume_state(&self) -> Result<VolumeState> {
         let output = Command::new("pactl")
-            .args(&["get-sink-volume", "@DEFAULT_SINK@"])
+            .args(["get-sink-volume", "@DEFAULT_SINK@"])
             .output()
             .context("Failed to get volume level")?;
 
@@ -319,18 +325,18 @@ impl SystemCollector {
         for line in output_str.lines() {
             if line.contains("Volume:") {
                 for part in line.split_whitespace() {
-                    if part.ends_with('%') {
-                        if let Ok(volume) = part.trim_end_matches('%').parse::<u8>() {
-                            level_percent = volume.min(100);
-                            break;
-                        }
+                    if part.ends_with('%')
+                        && let Ok(volume) = part.trim_end_matches('%').parse::<u8>()
+                    {
+                        level_percent = volume.min(100);
+                        break;
                     }
                 }
             }
         }
 
         let mute_output = Command::new("pactl")
-            .args(&["get-sink-mute", "@DEFAULT_SINK@"])
+            .args(["get-sink-mute", "@DEFAULT_SINK@"])
             .output()
             .context("Failed to get mute status")?;
 
@@ -380,7 +386,7 @@ impl SystemCollector {
 
     fn check_wifi_enabled(&self) -> Result<bool> {
         let output = Command::new("nmcli")
-            .args(&["radio", "wifi"])
+            .args(["radio", "wifi"])
             .output()
             .context("Failed to check WiFi status")?;
 
@@ -391,7 +397,7 @@ impl SystemCollector {
 
     fn check_bluetooth_enabled(&self) -> Result<bool> {
         let output = Command::new("bluetoothctl")
-            .args(&["show"])
+            .args(["show"])
             .output()
             .context("Failed to check Bluetooth status")?;
 
@@ -401,7 +407,7 @@ impl SystemCollector {
 
     fn check_vpn_connected(&self) -> Result<bool> {
         let output = Command::new("nmcli")
-            .args(&["connection", "show", "--active"])
+            .args(["connection", "show", "--active"])
             .output()
             .context("Failed to check VPN status")?;
 
@@ -1,24 +1,41 @@
 use anyhow::{Result, Context};
+use crate::instance;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     pub user: UserConfig,
     pub weather: WeatherConfig,
     pub shortcuts: ShortcutsConfig,
     pub network: NetworkConfig,
     pub hotkey: HotkeyConfig,
+    #[serde(default)]
+    pub ipc: IpcConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub actions: ActionsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub bar: BarConfig,
+    #[serde(default)]
+    pub dbus: DbusConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UserConfig {
     pub display_name: Option<String>,
     pub email: String,
     pub github_url: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WeatherConfig {
     pub location: String,
     pub api_key: Option<String>,
@@ -26,14 +43,14 @@ pub struct WeatherConfig {
     pub update_interval_minutes: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShortcutsConfig {
     pub left_links: Vec<LinkConfig>,
     pub launcher_command: String,
     pub browser_command: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LinkConfig {
     pub label: String,
     pub url: String,
@@ -41,17 +58,202 @@ pub struct LinkConfig {
     pub icon_path: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NetworkConfig {
     pub monitor_interface: Option<String>,
     pub vpn_name: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HotkeyConfig {
     pub toggle_overlay: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpcConfig {
+    pub max_message_bytes: usize,
+    /// Clients start `vacuum-launcher --daemon` when nothing is listening
+    pub autostart: bool,
+    /// How long clients keep retrying, with exponential backoff, for the daemon to come up
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: crate::ipc::DEFAULT_MAX_MESSAGE_BYTES,
+            autostart: false,
+            connect_timeout_secs: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityConfig {
+    /// Group whose members may connect besides the daemon's own user
+    pub allowed_group: Option<String>,
+    /// Commands members of `allowed_group` may send; everything else is owner-only.
+    /// Left out of a `[security]` table, the group may send nothing.
+    #[serde(default)]
+    pub group_commands: Vec<String>,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            allowed_group: None,
+            group_commands: vec![
+                "Hello".to_string(),
+                "GetState".to_string(),
+                "GetAudioVisualizer".to_string(),
+                "Subscribe".to_string(),
+                "GetHealth".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActionsConfig {
+    /// Seconds an action may run before its process is killed
+    pub default_timeout_secs: u64,
+    /// Logout, Reboot and Shutdown only run once confirmed with the token they return
+    pub confirm_power_actions: bool,
+    /// Seconds a confirmation token stays valid
+    pub confirmation_window_secs: u64,
+    /// Per-command overrides in seconds, keyed by command name (e.g. `ToggleVpn = 30`)
+    pub timeouts: BTreeMap<String, u64>,
+}
+
+impl Default for ActionsConfig {
+    fn default() -> Self {
+        Self {
+            default_timeout_secs: 10,
+            confirm_power_actions: true,
+            confirmation_window_secs: 30,
+            // Bringing a VPN up involves a handshake with a remote server
+            timeouts: BTreeMap::from([("ToggleVpn".to_string(), 30)]),
+        }
+    }
+}
+
+impl ActionsConfig {
+    pub fn timeout_for(&self, command: &str) -> Duration {
+        let secs = self.timeouts.get(command).copied().unwrap_or(self.default_timeout_secs);
+        Duration::from_secs(secs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// Loopback `address:port`, or `unix:/path/to/socket`
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:9477".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    /// JSON-lines log of state-changing commands; defaults to `~/.local/state/vacuum/audit.jsonl`,
+    /// or `~/.local/state/vacuum/<instance>/audit.jsonl` for a named instance
+    pub path: Option<PathBuf>,
+    /// The log is rotated once it would grow past this size
+    pub max_size_kb: u64,
+    /// Rotated logs kept next to it as `audit.jsonl.1` (newest) to `audit.jsonl.N`
+    pub max_files: u32,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: None,
+            max_size_kb: 1024,
+            max_files: 5,
+        }
+    }
+}
+
+impl AuditConfig {
+    pub fn log_path(&self) -> PathBuf {
+        if let Some(path) = &self.path {
+            return path.clone();
+        }
+        let mut path = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .unwrap_or_else(|| PathBuf::from("/tmp"));
+        path.push("vacuum");
+        if let Some(name) = &instance::current().name {
+            path.push(name);
+        }
+        path.push("audit.jsonl");
+        path
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DbusConfig {
+    /// Serve `dev.onyxdigital.Vacuum1` on the session bus
+    pub enabled: bool,
+}
+
+impl Default for DbusConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BarConfig {
+    /// Blocks in display order, out of `crate::bar::BLOCKS`
+    pub blocks: Vec<String>,
+    /// Put between blocks in waybar and polybar output
+    pub separator: String,
+    /// Text of each block with `{placeholder}`s; `volume_muted` and
+    /// `media_paused` apply in those states, and an empty result hides the block
+    pub templates: BTreeMap<String, String>,
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        Self {
+            blocks: crate::bar::BLOCKS.iter().map(|block| block.to_string()).collect(),
+            separator: " | ".to_string(),
+            templates: crate::bar::TEMPLATES.iter()
+                .map(|(name, template)| (name.to_string(), template.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl BarConfig {
+    /// The configured template for `name`, or the built-in one.
+    pub fn template(&self, name: &str) -> &str {
+        self.templates.get(name).map(String::as_str).unwrap_or_else(|| {
+            crate::bar::TEMPLATES.iter()
+                .find(|(template_name, _)| *template_name == name)
+                .map(|(_, template)| *template)
+                .unwrap_or_default()
+        })
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -97,14 +299,30 @@ impl Default for Config {
             hotkey: HotkeyConfig {
                 toggle_overlay: "Super+Shift+Space".to_string(),
             },
+            ipc: IpcConfig::default(),
+            security: SecurityConfig::default(),
+            actions: ActionsConfig::default(),
+            metrics: MetricsConfig::default(),
+            audit: AuditConfig::default(),
+            bar: BarConfig::default(),
+            dbus: DbusConfig::default(),
         }
     }
 }
 
+/// `--config`/`VACUUM_CONFIG` if given, else `~/.config/vacuum/config.toml`,
+/// or `~/.config/vacuum/<instance>/config.toml` for a named instance.
 pub fn get_config_path() -> Result<PathBuf> {
+    let instance = instance::current();
+    if let Some(path) = &instance.config {
+        return Ok(path.clone());
+    }
     let mut config_dir = dirs::config_dir()
         .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
     config_dir.push("vacuum");
+    if let Some(name) = &instance.name {
+        config_dir.push(name);
+    }
     std::fs::create_dir_all(&config_dir)
         .with_context(|| format!("Could not create vacuum config directory: {:?}", config_dir))?;
     config_dir.push("config.toml");
@@ -121,7 +339,12 @@ pub fn load_config() -> Result<Config> {
         return Ok(default_config);
     }
     
-    let config_content = std::fs::read_to_string(&config_path)
+    load_config_from(&config_path)
+}
+
+/// Reads, parses and validates an existing config file without creating one.
+pub fn load_config_from(config_path: &Path) -> Result<Config> {
+    let config_content = std::fs::read_to_string(config_path)
         .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
         
     let mut config: Config = toml::from_str(&config_content)
@@ -134,14 +357,98 @@ pub fn load_config() -> Result<Config> {
 }
 
 pub fn save_config(config: &Config) -> Result<()> {
-    let config_path = get_config_path()?;
+    save_config_to(&get_config_path()?, config)
+}
+
+pub fn save_config_to(config_path: &Path, config: &Config) -> Result<()> {
     let config_content = toml::to_string_pretty(config)
         .context("Failed to serialize config to TOML")?;
-    std::fs::write(&config_path, config_content)
+
+    // Write a sibling file and rename it so readers never see a half-written config
+    let tmp_path = config_path.with_extension("toml.tmp");
+    std::fs::write(&tmp_path, config_content)
+        .with_context(|| format!("Failed to write config file: {:?}", tmp_path))?;
+    std::fs::rename(&tmp_path, config_path)
         .with_context(|| format!("Failed to write config file: {:?}", config_path))?;
     Ok(())
 }
 
+/// Placeholder returned instead of secret values by `redacted_config_json`.
+pub const REDACTED: &str = "<redacted>";
+
+/// Write-only fields, as paths into the serialized config.
+const SECRET_FIELDS: &[&[&str]] = &[&["weather", "api_key"]];
+
+/// The config as JSON with every secret that is set replaced by `REDACTED`.
+pub fn redacted_config_json(config: &Config) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(config).context("Failed to serialize config")?;
+    redact_secrets(&mut value);
+    Ok(value)
+}
+
+/// Replaces every secret set in serialized config, or in a patch of it, with `REDACTED`.
+pub fn redact_secrets(value: &mut serde_json::Value) {
+    for path in SECRET_FIELDS {
+        if let Some(secret) = json_field_mut(value, path)
+            && !secret.is_null()
+        {
+            *secret = serde_json::Value::String(REDACTED.to_string());
+        }
+    }
+}
+
+/// Applies an RFC 7396 JSON merge patch and validates the result.
+/// Secrets patched with `REDACTED` keep their current value, so a client can
+/// send back what it read without wiping them.
+pub fn apply_config_patch(config: &Config, patch: &serde_json::Value) -> Result<Config> {
+    let mut patch = patch.clone();
+    for path in SECRET_FIELDS {
+        strip_redacted(&mut patch, path);
+    }
+
+    let mut value = serde_json::to_value(config).context("Failed to serialize config")?;
+    merge_patch(&mut value, &patch);
+
+    let mut patched: Config = serde_json::from_value(value).context("Patched config is invalid")?;
+    validate_and_fix_config(&mut patched)?;
+    Ok(patched)
+}
+
+fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch_fields) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let serde_json::Value::Object(target_fields) = target {
+        for (key, value) in patch_fields {
+            if value.is_null() {
+                target_fields.remove(key);
+            } else {
+                merge_patch(target_fields.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+            }
+        }
+    }
+}
+
+fn json_field_mut<'a>(value: &'a mut serde_json::Value, path: &[&str]) -> Option<&'a mut serde_json::Value> {
+    path.iter().try_fold(value, |value, key| value.get_mut(*key))
+}
+
+fn strip_redacted(patch: &mut serde_json::Value, path: &[&str]) {
+    let Some((field, parents)) = path.split_last() else {
+        return;
+    };
+    if let Some(serde_json::Value::Object(fields)) = json_field_mut(patch, parents)
+        && fields.get(*field).and_then(|value| value.as_str()) == Some(REDACTED)
+    {
+        fields.remove(*field);
+    }
+}
+
 fn validate_and_fix_config(config: &mut Config) -> Result<()> {
     // Validate URLs
     for link in &config.shortcuts.left_links {
@@ -185,10 +492,145 @@ fn validate_and_fix_config(config: &mut Config) -> Result<()> {
         config.shortcuts.browser_command = "firefox".to_string();
     }
     
+    // Validate IPC frame limit - a GetState response needs at least a few KiB
+    if config.ipc.max_message_bytes < 64 * 1024 {
+        tracing::warn!("IPC max message size too small ({}), using default", config.ipc.max_message_bytes);
+        config.ipc.max_message_bytes = crate::ipc::DEFAULT_MAX_MESSAGE_BYTES;
+    }
+    
+    // Validate group command policy against known commands
+    config.security.group_commands.retain(|name| {
+        let known = crate::daemon::IpcCommand::NAMES.contains(&name.as_str());
+        if !known {
+            tracing::warn!("Unknown command in security.group_commands: {}", name);
+        }
+        known
+    });
+    
+    // Validate action timeouts - zero would fail every action immediately
+    if config.actions.default_timeout_secs == 0 {
+        tracing::warn!("Default action timeout cannot be 0, setting to 10 seconds");
+        config.actions.default_timeout_secs = 10;
+    }
+    if config.actions.confirmation_window_secs == 0 {
+        tracing::warn!("Confirmation window cannot be 0, setting to 30 seconds");
+        config.actions.confirmation_window_secs = 30;
+    }
+    config.actions.timeouts.retain(|name, secs| {
+        let known = crate::daemon::IpcCommand::NAMES.contains(&name.as_str());
+        if !known {
+            tracing::warn!("Unknown command in actions.timeouts: {}", name);
+        } else if *secs == 0 {
+            tracing::warn!("Timeout for {} cannot be 0, using the default", name);
+        }
+        known && *secs > 0
+    });
+    
+    // Validate metrics exporter address
+    if config.metrics.enabled
+        && let Err(e) = crate::metrics::MetricsListen::parse(&config.metrics.listen)
+    {
+        tracing::warn!("Disabling metrics exporter: {:#}", e);
+        config.metrics.enabled = false;
+    }
+    
+    // Validate audit log rotation - a zero size would rotate on every record
+    if config.audit.max_size_kb == 0 {
+        tracing::warn!("Audit log size limit cannot be 0, setting to 1024 KiB");
+        config.audit.max_size_kb = 1024;
+    }
+    if config.audit.max_files == 0 {
+        tracing::warn!("Audit log must keep at least one rotated file, setting to 5");
+        config.audit.max_files = 5;
+    }
+    
+    // Validate bar blocks and templates against the blocks the bar knows
+    config.bar.blocks.retain(|name| {
+        let known = crate::bar::BLOCKS.contains(&name.as_str());
+        if !known {
+            tracing::warn!("Unknown block in bar.blocks: {}", name);
+        }
+        known
+    });
+    config.bar.templates.retain(|name, _| {
+        let known = crate::bar::TEMPLATES.iter().any(|(template_name, _)| template_name == name);
+        if !known {
+            tracing::warn!("Unknown template in bar.templates: {}", name);
+        }
+        known
+    });
+    
     // Validate hotkey format (basic check)
     if !config.hotkey.toggle_overlay.contains('+') && !config.hotkey.toggle_overlay.starts_with("Super") {
         tracing::warn!("Hotkey format may be invalid: {}", config.hotkey.toggle_overlay);
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config_from_rejects_invalid_links() {
+        let path = std::env::temp_dir().join(format!("vacuum-config-test-{}.toml", std::process::id()));
+
+        let mut config = Config::default();
+        std::fs::write(&path, toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(load_config_from(&path).unwrap(), config);
+
+        config.shortcuts.left_links[0].url = "ftp://example.com".to_string();
+        std::fs::write(&path, toml::to_string_pretty(&config).unwrap()).unwrap();
+        let result = load_config_from(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_secrets_are_redacted_and_write_only() {
+        let config = Config::default();
+
+        let value = redacted_config_json(&config).unwrap();
+        assert_eq!(value["weather"]["api_key"], REDACTED);
+
+        // Sending the redacted value back keeps the secret
+        let patched = apply_config_patch(&config, &value).unwrap();
+        assert_eq!(patched.weather.api_key, config.weather.api_key);
+
+        let patch = serde_json::json!({ "weather": { "api_key": "new-key", "location": "Oslo" } });
+        let patched = apply_config_patch(&config, &patch).unwrap();
+        assert_eq!(patched.weather.api_key.as_deref(), Some("new-key"));
+        assert_eq!(patched.weather.location, "Oslo");
+        assert_eq!(patched.weather.provider, config.weather.provider);
+    }
+
+    #[test]
+    fn test_merge_patch_removes_null_fields() {
+        let config = Config::default();
+
+        let patch = serde_json::json!({ "weather": { "api_key": null } });
+        let patched = apply_config_patch(&config, &patch).unwrap();
+        assert_eq!(patched.weather.api_key, None);
+
+        let patch = serde_json::json!({ "shortcuts": { "left_links": [
+            { "label": "", "url": "https://example.com", "icon_name": "x" }
+        ] } });
+        assert!(apply_config_patch(&config, &patch).is_err());
+    }
+
+    #[test]
+    fn test_partial_metrics_table_uses_defaults() {
+        let metrics: MetricsConfig = toml::from_str("enabled = true").unwrap();
+        assert!(metrics.enabled);
+        assert_eq!(metrics.listen, MetricsConfig::default().listen);
+    }
+
+    #[test]
+    fn test_partial_security_table_grants_no_commands() {
+        let security: SecurityConfig = toml::from_str("allowed_group = \"wheel\"").unwrap();
+        assert_eq!(security.allowed_group.as_deref(), Some("wheel"));
+        assert!(security.group_commands.is_empty());
+    }
+}
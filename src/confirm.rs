@@ -0,0 +1,78 @@
+use crate::daemon::IpcCommand;
+use anyhow::{Result, Context};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Power commands waiting for `ConfirmAction`, keyed by their one-time token.
+/// Tokens are daemon-wide so a second client invocation can confirm them.
+#[derive(Clone, Default)]
+pub struct PendingConfirmations {
+    pending: Arc<Mutex<HashMap<String, PendingAction>>>,
+}
+
+struct PendingAction {
+    command: IpcCommand,
+    expires_at: Instant,
+}
+
+impl PendingConfirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parks `command` and returns the token that runs it within `window`.
+    pub fn request(&self, command: IpcCommand, window: Duration) -> Result<String> {
+        let token = new_token()?;
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, action| action.expires_at > Instant::now());
+        pending.insert(token.clone(), PendingAction { command, expires_at: Instant::now() + window });
+        Ok(token)
+    }
+
+    /// Consumes a token, returning the command it was issued for if it is still live.
+    pub fn take(&self, token: &str) -> Option<IpcCommand> {
+        let action = self.pending.lock().unwrap().remove(token)?;
+        if action.expires_at <= Instant::now() {
+            return None;
+        }
+        Some(action.command)
+    }
+
+    /// Drops a token without running its command. Returns whether it was still live.
+    pub fn cancel(&self, token: &str) -> bool {
+        self.take(token).is_some()
+    }
+}
+
+/// 128 random bits, hex encoded, so tokens can't be guessed by other scripts.
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut bytes))
+        .context("Failed to generate a confirmation token")?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_single_use_and_expire() {
+        let confirmations = PendingConfirmations::new();
+
+        let token = confirmations.request(IpcCommand::Reboot, Duration::from_secs(30)).unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(matches!(confirmations.take(&token), Some(IpcCommand::Reboot)));
+        assert!(confirmations.take(&token).is_none());
+
+        let expired = confirmations.request(IpcCommand::Shutdown, Duration::ZERO).unwrap();
+        assert!(confirmations.take(&expired).is_none());
+
+        let cancelled = confirmations.request(IpcCommand::Logout, Duration::from_secs(30)).unwrap();
+        assert!(confirmations.cancel(&cancelled));
+        assert!(confirmations.take(&cancelled).is_none());
+    }
+}
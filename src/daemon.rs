@@ -1,19 +1,40 @@
-use crate::state::VacuumState;
-use crate::config::{Config, load_config};
+use crate::state::{VacuumState, StateSection, StateUpdate, VolumeState, Toggles, LauncherShortcuts, LinkButton, PowerAction, ScheduledAction, unix_time_ms};
+use crate::config::{self, Config, get_config_path, load_config, load_config_from};
 use crate::collectors::SystemCollector;
 use crate::actions::ActionHandler;
+use crate::ipc;
+use crate::auth::{self, PeerPolicy, PeerRole};
+use crate::cava::VisualizerBackend;
+use crate::client::IpcClient;
+use crate::systemd;
+use crate::metrics;
+use crate::dbus;
+use crate::instance::{self, InstanceLock};
+use crate::confirm::PendingConfirmations;
+use crate::schedule::{PowerSchedule, ScheduleTime};
+use crate::audit::{self, AuditLog, AuditRecord, Caller};
+use crate::health::{self, HealthReport, LoopHealth, LoopHeartbeats, AUDIO_LOOP, NETWORK_LOOP, SYSTEM_LOOP, WEATHER_LOOP};
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::{UnixListener, UnixStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::{RwLock, broadcast};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{RwLock, broadcast, mpsc, oneshot, watch};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum IpcCommand {
+    /// Handshake; answered with `IpcResponse::Hello` describing the daemon.
+    Hello { protocol_version: u32, client_name: String },
+    /// Flips overlay visibility; answered with `ToggleResult(visible)`.
     ToggleOverlay,
+    ShowOverlay,
+    HideOverlay,
+    /// Registers an overlay frontend for this connection and subscribes it to
+    /// `Overlay` state changes until it disconnects.
+    RegisterOverlay { name: String },
     GetState,
     GetAudioVisualizer,
     SetVolume(u8),
@@ -21,94 +42,440 @@ pub enum IpcCommand {
     ToggleWifi,
     ToggleBluetooth,
     ToggleVpn,
+    /// Power commands answer `ConfirmationRequired` when `[actions]
+    /// confirm_power_actions` is on, and only run once confirmed.
     Logout,
     Reboot,
     Shutdown,
     LaunchApp,
     LaunchUrl(String),
+    /// Keep pushing `StateChanged` replies for the given sections (all if empty).
+    Subscribe { sections: Vec<StateSection> },
+    /// Current config as JSON, with secrets redacted.
+    GetConfig,
+    /// RFC 7396 JSON merge patch; validated, saved and applied immediately.
+    PatchConfig(serde_json::Value),
+    ReloadConfig,
+    /// Freshness, last error and source of every section plus update loop status.
+    GetHealth,
+    /// Runs the power command a `ConfirmationRequired` token was issued for.
+    ConfirmAction(String),
+    CancelAction(String),
+    /// Runs a power action later; the pending action shows up in `VacuumState`
+    /// with a countdown and survives daemon restarts.
+    ScheduleShutdown(ScheduleTime),
+    ScheduleReboot(ScheduleTime),
+    CancelScheduled,
+    /// Version, PID, uptime, paths, update loops and connected clients.
+    Status,
+    /// Shuts the daemon down cleanly, as SIGTERM does; used by `--replace`.
+    StopDaemon,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum IpcResponse {
     Success,
-    State(VacuumState),
+    State(Box<VacuumState>),
     AudioVisualizer(crate::cava::AudioVisualizerData),
     Error(String),
     ToggleResult(bool),
+    StateChanged(StateUpdate),
+    Hello(DaemonInfo),
+    Config(serde_json::Value),
+    /// The action outlived its `[actions]` timeout and its process was killed.
+    ActionTimedOut { command: String, timeout_ms: u64 },
+    Health(HealthReport),
+    /// Send `ConfirmAction(token)` within `expires_in_ms` to run `command`.
+    ConfirmationRequired { token: String, command: String, expires_in_ms: u64 },
+    Status(DaemonStatus),
+}
+
+/// What a daemon supports, so clients can adapt instead of failing on
+/// commands or responses they don't know about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    pub daemon_version: String,
+    pub protocol_version: u32,
+    pub commands: Vec<String>,
+    pub features: Vec<String>,
+    pub visualizer: VisualizerBackend,
+    pub collectors: Vec<StateSection>,
+}
+
+/// Answer to `Status`: what is running and how its update loops are doing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub daemon_version: String,
+    pub pid: u32,
+    pub uptime_secs: u64,
+    pub socket_path: PathBuf,
+    pub config_path: PathBuf,
+    /// Interval, last tick and last run duration of each update loop
+    pub loops: Vec<LoopHealth>,
+    /// Open IPC connections, including the one asking
+    pub clients: usize,
+    /// `cava_enabled` or `cava_disabled`, as decided by build.rs
+    pub cava: String,
+}
+
+impl IpcCommand {
+    /// Names of every command variant, as they appear on the wire.
+    pub const NAMES: &'static [&'static str] = &[
+        "Hello",
+        "ToggleOverlay",
+        "ShowOverlay",
+        "HideOverlay",
+        "RegisterOverlay",
+        "GetState",
+        "GetAudioVisualizer",
+        "SetVolume",
+        "ToggleMute",
+        "ToggleWifi",
+        "ToggleBluetooth",
+        "ToggleVpn",
+        "Logout",
+        "Reboot",
+        "Shutdown",
+        "LaunchApp",
+        "LaunchUrl",
+        "Subscribe",
+        "GetConfig",
+        "PatchConfig",
+        "ReloadConfig",
+        "GetHealth",
+        "ConfirmAction",
+        "CancelAction",
+        "ScheduleShutdown",
+        "ScheduleReboot",
+        "CancelScheduled",
+        "Status",
+        "StopDaemon",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IpcCommand::Hello { .. } => "Hello",
+            IpcCommand::ToggleOverlay => "ToggleOverlay",
+            IpcCommand::ShowOverlay => "ShowOverlay",
+            IpcCommand::HideOverlay => "HideOverlay",
+            IpcCommand::RegisterOverlay { .. } => "RegisterOverlay",
+            IpcCommand::GetState => "GetState",
+            IpcCommand::GetAudioVisualizer => "GetAudioVisualizer",
+            IpcCommand::SetVolume(_) => "SetVolume",
+            IpcCommand::ToggleMute => "ToggleMute",
+            IpcCommand::ToggleWifi => "ToggleWifi",
+            IpcCommand::ToggleBluetooth => "ToggleBluetooth",
+            IpcCommand::ToggleVpn => "ToggleVpn",
+            IpcCommand::Logout => "Logout",
+            IpcCommand::Reboot => "Reboot",
+            IpcCommand::Shutdown => "Shutdown",
+            IpcCommand::LaunchApp => "LaunchApp",
+            IpcCommand::LaunchUrl(_) => "LaunchUrl",
+            IpcCommand::Subscribe { .. } => "Subscribe",
+            IpcCommand::GetConfig => "GetConfig",
+            IpcCommand::PatchConfig(_) => "PatchConfig",
+            IpcCommand::ReloadConfig => "ReloadConfig",
+            IpcCommand::GetHealth => "GetHealth",
+            IpcCommand::ConfirmAction(_) => "ConfirmAction",
+            IpcCommand::CancelAction(_) => "CancelAction",
+            IpcCommand::ScheduleShutdown(_) => "ScheduleShutdown",
+            IpcCommand::ScheduleReboot(_) => "ScheduleReboot",
+            IpcCommand::CancelScheduled => "CancelScheduled",
+            IpcCommand::Status => "Status",
+            IpcCommand::StopDaemon => "StopDaemon",
+        }
+    }
+}
+
+fn daemon_info(config: &Config) -> DaemonInfo {
+    let mut collectors = StateSection::ALL.to_vec();
+    // Overlay visibility and scheduled actions are driven by commands, not collected
+    collectors.retain(|section| !matches!(section, StateSection::Overlay | StateSection::ScheduledAction));
+    if config.weather.api_key.is_none() {
+        // Weather falls back to static placeholder data without an API key
+        collectors.retain(|section| *section != StateSection::WeatherInfo);
+    }
+
+    DaemonInfo {
+        daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: ipc::PROTOCOL_VERSION,
+        commands: IpcCommand::NAMES.iter().map(|name| name.to_string()).collect(),
+        features: vec![
+            "framed-messages".to_string(),
+            "request-ids".to_string(),
+            "subscriptions".to_string(),
+            "config-editing".to_string(),
+            "overlay-state".to_string(),
+            "action-timeouts".to_string(),
+            "health".to_string(),
+            "power-confirmation".to_string(),
+            "scheduled-power-actions".to_string(),
+            "audit-log".to_string(),
+            "dbus".to_string(),
+            "status".to_string(),
+        ],
+        visualizer: crate::cava::BACKEND,
+        collectors,
+    }
+}
+
+/// A command tagged with a client-chosen ID. Many requests can be in flight
+/// on one connection; the daemon echoes the ID so replies may arrive out of order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcRequest {
+    pub id: u64,
+    pub command: IpcCommand,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcReply {
+    pub id: u64,
+    pub response: IpcResponse,
 }
 
 pub struct VacuumDaemon {
-    config: Config,
+    config: watch::Sender<Arc<Config>>,
+    config_path: PathBuf,
+    /// Serialises config patches
+    config_writes: Arc<tokio::sync::Mutex<()>>,
     state: Arc<RwLock<VacuumState>>,
     shutdown_tx: broadcast::Sender<()>,
+    state_events: broadcast::Sender<StateUpdate>,
+    heartbeats: LoopHeartbeats,
+    confirmations: PendingConfirmations,
+    schedule: PowerSchedule,
+    audit: AuditLog,
+    started: Instant,
+    clients: Arc<AtomicUsize>,
+    replace: bool,
+    state_writes: mpsc::Sender<StateWrite>,
+    /// Handed to the state owner task when the update loops start
+    state_writes_rx: Option<mpsc::Receiver<StateWrite>>,
+}
+
+/// Shared handles every IPC connection needs.
+#[derive(Clone)]
+struct IpcContext {
+    state: Arc<RwLock<VacuumState>>,
+    state_writes: mpsc::Sender<StateWrite>,
+    config: watch::Sender<Arc<Config>>,
+    config_path: PathBuf,
+    config_writes: Arc<tokio::sync::Mutex<()>>,
+    actions: ActionHandler,
+    state_events: broadcast::Sender<StateUpdate>,
+    policy: Arc<PeerPolicy>,
+    peer: PeerRole,
+    caller: Caller,
+    heartbeats: LoopHeartbeats,
+    confirmations: PendingConfirmations,
+    schedule: PowerSchedule,
+    audit: AuditLog,
+    started: Instant,
+    clients: Arc<AtomicUsize>,
+    shutdown: broadcast::Sender<()>,
+}
+
+impl IpcContext {
+    /// Derives a section update from the current state and publishes it if it changed.
+    async fn update_state(&self, source: &str, update: impl FnOnce(&VacuumState) -> StateUpdate + Send + 'static) {
+        derive_update(&self.state_writes, source, |state| (Some(update(state)), ())).await;
+    }
+
+    /// Sets overlay visibility from its current value and returns the result.
+    /// The change timestamp only moves when visibility actually flips.
+    async fn set_overlay_visible(&self, source: &str, visible: impl FnOnce(bool) -> bool + Send + 'static) -> bool {
+        let new_visible = derive_update(&self.state_writes, source, |state| {
+            let mut overlay = state.overlay.clone();
+            let new_visible = visible(overlay.visible);
+            if new_visible == overlay.visible {
+                return (None, new_visible);
+            }
+            overlay.visible = new_visible;
+            overlay.changed_at_ms = unix_time_ms();
+            (Some(StateUpdate::Overlay(overlay)), new_visible)
+        }).await;
+        // The owner only stops while the daemon shuts down
+        new_visible.unwrap_or(false)
+    }
+
+    /// Records a state-changing command and its outcome in the audit log.
+    async fn audit(&self, command: &IpcCommand, response: &IpcResponse) {
+        if !audit::is_audited(command) {
+            return;
+        }
+        let config = self.config.borrow().audit.clone();
+        self.audit.record(&config, AuditRecord::new(self.caller, command, response)).await;
+    }
+
+    /// Audits `command` once it answers, or as cancelled if it never does.
+    fn audit_guard(&self, command: &IpcCommand) -> AuditGuard {
+        AuditGuard {
+            context: self.clone(),
+            command: audit::is_audited(command).then(|| command.clone()),
+        }
+    }
+
+    async fn update_overlay_frontends(&self, update: impl FnOnce(&mut Vec<String>) + Send + 'static) {
+        self.update_state("RegisterOverlay", |state| {
+            let mut overlay = state.overlay.clone();
+            update(&mut overlay.frontends);
+            StateUpdate::Overlay(overlay)
+        }).await;
+    }
+}
+
+/// Writes a command's audit record when it finishes, or a `cancelled` one if
+/// its task is dropped first, so a client hanging up mid-action still leaves
+/// a trace of what it started.
+struct AuditGuard {
+    context: IpcContext,
+    command: Option<IpcCommand>,
+}
+
+impl AuditGuard {
+    async fn finish(mut self, response: &IpcResponse) {
+        if let Some(command) = self.command.take() {
+            self.context.audit(&command, response).await;
+        }
+    }
+}
+
+impl Drop for AuditGuard {
+    fn drop(&mut self) {
+        if let Some(command) = self.command.take() {
+            let config = self.context.config.borrow().audit.clone();
+            self.context.audit.record_later(&config, AuditRecord::cancelled(self.context.caller, &command));
+        }
+    }
+}
+
+/// Stores a section in the state and notifies subscribers when it changed.
+fn publish(state: &mut VacuumState, events: &broadcast::Sender<StateUpdate>, update: StateUpdate) {
+    if state.apply(&update) {
+        // No receivers just means nobody is subscribed right now
+        let _ = events.send(update);
+    }
 }
 
 impl VacuumDaemon {
     pub fn new() -> Result<Self> {
-        let config = load_config()?;
+        let config_path = get_config_path()?;
+        let (config, _) = watch::channel(Arc::new(load_config()?));
         let state = Arc::new(RwLock::new(VacuumState::default()));
         let (shutdown_tx, _) = broadcast::channel(16);
+        let (state_events, _) = broadcast::channel(64);
+        let (state_writes, state_writes_rx) = mpsc::channel(STATE_UPDATE_BUFFER);
 
         Ok(Self {
             config,
+            config_path,
+            config_writes: Arc::new(tokio::sync::Mutex::new(())),
             state,
             shutdown_tx,
+            state_events,
+            heartbeats: LoopHeartbeats::new(),
+            confirmations: PendingConfirmations::new(),
+            schedule: PowerSchedule::load(get_schedule_path()),
+            audit: AuditLog::new(),
+            started: Instant::now(),
+            clients: Arc::new(AtomicUsize::new(0)),
+            replace: false,
+            state_writes,
+            state_writes_rx: Some(state_writes_rx),
         })
     }
 
+    /// Makes `run` stop an already running daemon and take its place
+    /// instead of failing.
+    pub fn replacing(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         tracing::info!("Starting Vacuum Launcher daemon");
 
-        // Ensure only one instance using atomic socket binding
         let socket_path = get_socket_path();
         let pid_path = get_pid_path();
-        
-        // First, try to connect to existing socket to see if daemon is alive
-        if socket_path.exists() {
-            match UnixStream::connect(&socket_path).await {
-                Ok(_) => {
-                    // Connection successful - daemon is running
-                    return Err(anyhow::anyhow!("Daemon already running"));
-                }
-                Err(_) => {
-                    // Connection failed - daemon is dead, remove stale socket
-                    tracing::info!("Removing stale socket file");
-                    std::fs::remove_file(&socket_path)?;
-                }
-            }
+        let policy = Arc::new(PeerPolicy::from_config(&self.config.borrow().security));
+
+        // The socket lives in a directory only we (and the allowed group) can
+        // enter; an explicit --socket goes wherever its owner put it
+        if instance::current().socket.is_none() {
+            auth::prepare_runtime_dir(&get_runtime_dir(), policy.group_gid())?;
         }
 
-        // Now attempt to bind - this is atomic and prevents race conditions
-        let listener = UnixListener::bind(&socket_path)
-            .with_context(|| {
-                format!("Failed to bind socket at {:?}. Another daemon may be starting simultaneously.", socket_path)
-            })?;
+        // One daemon per socket: whoever holds the PID file lock owns the socket
+        let mut instance = match InstanceLock::try_acquire(&pid_path)? {
+            Some(instance) => instance,
+            None if self.replace => {
+                tracing::info!("Replacing the running daemon");
+                instance::take_over(&pid_path).await?
+            }
+            None => {
+                let pid = instance::read_pid(&pid_path).map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+                return Err(anyhow::anyhow!("Daemon already running{}; use --replace to take over", pid));
+            }
+        };
+        
+        // Under socket activation systemd owns the socket file; otherwise bind our own
+        let activated = systemd::listen_socket()?;
+        let owns_socket = activated.is_none();
+        let listener = match activated {
+            Some(listener) => {
+                tracing::info!("Using socket passed in by systemd");
+                UnixListener::from_std(listener).context("Failed to adopt socket from systemd")?
+            }
+            None => bind_socket(&socket_path, policy.group_gid()).await?,
+        };
         
-        // Successfully bound - write our PID for diagnostics
-        std::fs::write(&pid_path, std::process::id().to_string())
-            .context("Failed to write PID file")?;
+        instance.write_pid()?;
 
         // Start update loops
         self.start_update_loops().await;
+        self.spawn_config_watcher();
+        self.spawn_service_notifier();
+        self.spawn_metrics_exporter();
+        self.spawn_power_scheduler();
+        let dbus_service = self.spawn_dbus_service(&policy);
 
         tracing::info!("Daemon listening on socket: {:?}", socket_path);
 
         // Set up signal handling
         let mut shutdown_rx = self.shutdown_tx.subscribe();
+        self.spawn_signal_handlers()?;
         
         // Accept IPC connections with graceful shutdown
         let accept_loop = async {
             loop {
                 match listener.accept().await {
                     Ok((stream, _)) => {
-                        let state = self.state.clone();
-                        let config = self.config.clone();
-                        let actions = ActionHandler::new();
-                        
+                        let (peer, caller) = match stream.peer_cred() {
+                            Ok(cred) => match policy.authorize(cred.uid(), cred.gid()) {
+                                Some(peer) => (peer, Caller { uid: cred.uid(), pid: cred.pid() }),
+                                None => {
+                                    tracing::warn!(
+                                        "Rejecting IPC connection from uid {} (pid {:?})",
+                                        cred.uid(), cred.pid()
+                                    );
+                                    continue;
+                                }
+                            },
+                            Err(e) => {
+                                tracing::warn!("Rejecting IPC connection without peer credentials: {}", e);
+                                continue;
+                            }
+                        };
+
+                        let context = self.ipc_context(&policy, peer, caller);
+
                         tokio::spawn(async move {
-                            if let Err(e) = handle_ipc_connection(stream, state, config, actions).await {
+                            let clients = context.clients.clone();
+                            clients.fetch_add(1, Ordering::Relaxed);
+                            if let Err(e) = handle_ipc_connection(stream, context).await {
                                 tracing::error!("IPC connection error: {}", e);
                             }
+                            clients.fetch_sub(1, Ordering::Relaxed);
                         });
                     }
                     Err(e) => {
@@ -126,47 +493,335 @@ impl VacuumDaemon {
         }
 
         // Cleanup
-        let _ = std::fs::remove_file(&socket_path);
-        let _ = std::fs::remove_file(&get_pid_path());
+        let _ = systemd::notify("STOPPING=1");
+        if owns_socket {
+            let _ = std::fs::remove_file(&socket_path);
+        }
+        // A replacement waits for the lock, so give up the bus name before it
+        let _ = tokio::time::timeout(Duration::from_secs(2), dbus_service).await;
+        drop(instance);
         tracing::info!("Daemon shutting down gracefully");
         Ok(())
     }
 
-    async fn start_update_loops(&mut self) {
+    fn ipc_context(&self, policy: &Arc<PeerPolicy>, peer: PeerRole, caller: Caller) -> IpcContext {
+        IpcContext {
+            state: self.state.clone(),
+            state_writes: self.state_writes.clone(),
+            config: self.config.clone(),
+            config_path: self.config_path.clone(),
+            config_writes: self.config_writes.clone(),
+            actions: ActionHandler::new(),
+            state_events: self.state_events.clone(),
+            policy: policy.clone(),
+            peer,
+            caller,
+            heartbeats: self.heartbeats.clone(),
+            confirmations: self.confirmations.clone(),
+            schedule: self.schedule.clone(),
+            audit: self.audit.clone(),
+            started: self.started,
+            clients: self.clients.clone(),
+            shutdown: self.shutdown_tx.clone(),
+        }
+    }
+
+    /// SIGTERM and SIGINT broadcast shutdown so every loop stops and the socket
+    /// and PID file get cleaned up; SIGHUP reloads config.toml.
+    fn spawn_signal_handlers(&self) -> Result<()> {
+        let mut sigterm = signal(SignalKind::terminate()).context("Failed to install SIGTERM handler")?;
+        let mut sigint = signal(SignalKind::interrupt()).context("Failed to install SIGINT handler")?;
+        let mut sighup = signal(SignalKind::hangup()).context("Failed to install SIGHUP handler")?;
+        let shutdown_tx = self.shutdown_tx.clone();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let config = self.config.clone();
+        let config_path = self.config_path.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = sigterm.recv() => {
+                        tracing::info!("Received SIGTERM");
+                        let _ = shutdown_tx.send(());
+                        break;
+                    }
+                    _ = sigint.recv() => {
+                        tracing::info!("Received SIGINT");
+                        let _ = shutdown_tx.send(());
+                        break;
+                    }
+                    _ = sighup.recv() => {
+                        tracing::info!("Received SIGHUP, reloading configuration");
+                        if let Err(e) = reload_config(&config, &config_path) {
+                            tracing::error!("Failed to reload configuration, keeping the previous one: {:#}", e);
+                        }
+                    }
+                    _ = shutdown_rx.recv() => break,
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Tells systemd we're ready once every gating loop has collected once,
+    /// then keeps the watchdog fed for as long as none of the loops stall.
+    fn spawn_service_notifier(&self) {
+        let heartbeats = self.heartbeats.clone();
+        let mut ready_rx = self.heartbeats.ready();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = ready_rx.wait_for(|ready| *ready) => {},
+                _ = shutdown_rx.recv() => return,
+            }
+            match systemd::notify("READY=1") {
+                Ok(true) => tracing::info!("Notified systemd that the daemon is ready"),
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to notify systemd: {:#}", e),
+            }
+
+            let Some(period) = systemd::watchdog_interval() else {
+                return;
+            };
+            tracing::info!("Sending watchdog pings every {:?}", period);
+            let mut interval = tokio::time::interval(period);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let stalled = heartbeats.stalled();
+                        if !stalled.is_empty() {
+                            tracing::warn!("Withholding watchdog ping, stalled update loops: {:?}", stalled);
+                            continue;
+                        }
+                        if let Err(e) = systemd::notify("WATCHDOG=1") {
+                            tracing::warn!("Failed to ping systemd watchdog: {:#}", e);
+                        }
+                    },
+                    _ = shutdown_rx.recv() => break,
+                }
+            }
+        });
+    }
+
+    /// Runs the OpenMetrics exporter while `[metrics]` enables it, restarting
+    /// it whenever that section of the config changes.
+    fn spawn_metrics_exporter(&self) {
         let state = self.state.clone();
+        let mut config_rx = self.config.subscribe();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut metrics = config_rx.borrow_and_update().metrics.clone();
+            loop {
+                let listen = match metrics::MetricsListen::parse(&metrics.listen) {
+                    Ok(listen) if metrics.enabled => Some(listen),
+                    _ => None,
+                };
+                let exporter = listen.clone().map(|listen| {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = metrics::serve(&listen, state).await {
+                            tracing::error!("Metrics exporter stopped: {:#}", e);
+                        }
+                    })
+                });
+
+                let shutting_down = loop {
+                    tokio::select! {
+                        Ok(()) = config_rx.changed() => {
+                            let new_metrics = config_rx.borrow_and_update().metrics.clone();
+                            if new_metrics != metrics {
+                                metrics = new_metrics;
+                                break false;
+                            }
+                        },
+                        _ = shutdown_rx.recv() => break true,
+                    }
+                };
+
+                if let Some(exporter) = exporter {
+                    exporter.abort();
+                    let _ = exporter.await;
+                }
+                if let Some(metrics::MetricsListen::Unix(path)) = listen {
+                    let _ = std::fs::remove_file(path);
+                }
+                if shutting_down {
+                    break;
+                }
+                tracing::info!("Metrics exporter picked up new configuration");
+            }
+        });
+    }
+
+    /// Serves the D-Bus interface while `[dbus]` enables it, registering again
+    /// whenever that section of the config changes. Without a session bus the
+    /// daemon carries on with just its socket.
+    fn spawn_dbus_service(&self, policy: &Arc<PeerPolicy>) -> tokio::task::JoinHandle<()> {
+        // D-Bus callers are checked against the owner uid by the interface itself
+        let context = self.ipc_context(policy, PeerRole::Owner, Caller::daemon());
+        let mut config_rx = self.config.subscribe();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut dbus_config = config_rx.borrow_and_update().dbus.clone();
+            loop {
+                let service = if dbus_config.enabled {
+                    start_dbus_service(&context).await
+                        .inspect_err(|e| tracing::warn!("D-Bus service unavailable: {:#}", e))
+                        .ok()
+                } else {
+                    None
+                };
+
+                let shutting_down = loop {
+                    tokio::select! {
+                        Ok(()) = config_rx.changed() => {
+                            let new_config = config_rx.borrow_and_update().dbus.clone();
+                            if new_config != dbus_config {
+                                dbus_config = new_config;
+                                break false;
+                            }
+                        },
+                        _ = shutdown_rx.recv() => break true,
+                    }
+                };
+
+                if let Some((connection, forwarder)) = service {
+                    forwarder.abort();
+                    let _ = connection.release_name(dbus::bus_name()).await;
+                }
+                if shutting_down {
+                    break;
+                }
+                tracing::info!("D-Bus service picked up new configuration");
+            }
+        })
+    }
+
+    /// Publishes the scheduled power action with its countdown every second
+    /// and runs it once it comes due.
+    fn spawn_power_scheduler(&self) {
+        let schedule = self.schedule.clone();
+        let mut schedule_rx = self.schedule.subscribe();
+        let audit = self.audit.clone();
+        let state_writes = self.state_writes.clone();
         let config = self.config.clone();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    Ok(()) = schedule_rx.changed() => {},
+                    _ = shutdown_rx.recv() => break,
+                }
+
+                let now_ms = unix_time_ms();
+                let scheduled = schedule_rx.borrow_and_update().clone();
+                let countdown = scheduled.as_ref()
+                    .map(|scheduled| ScheduledAction::new(scheduled.action, scheduled.due_ms, now_ms));
+                derive_update(&state_writes, "scheduler", move |state| {
+                    let changed = state.scheduled_action != countdown;
+                    (changed.then_some(StateUpdate::ScheduledAction(countdown)), ())
+                }).await;
+
+                let Some(due) = scheduled.filter(|scheduled| scheduled.due_ms <= now_ms) else {
+                    continue;
+                };
+                schedule.cancel();
+                tracing::warn!("Running scheduled {:?}", due.action);
+                let command = match due.action {
+                    PowerAction::Shutdown => IpcCommand::Shutdown,
+                    PowerAction::Reboot => IpcCommand::Reboot,
+                };
+                let config = config.borrow().clone();
+                let response = run_power_action(&command, &config, &ActionHandler::new()).await;
+                audit.record(&config.audit, AuditRecord::new(Caller::daemon(), &command, &response)).await;
+                if !matches!(response, IpcResponse::Success) {
+                    tracing::error!("Scheduled {:?} failed: {:?}", due.action, response);
+                }
+            }
+        });
+    }
+
+    /// Polls config.toml and swaps in the new config whenever the file changes.
+    fn spawn_config_watcher(&self) {
+        let config = self.config.clone();
+        let config_path = self.config_path.clone();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut last_seen = config_file_stamp(&config_path);
+            let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let stamp = config_file_stamp(&config_path);
+                        if stamp == last_seen {
+                            continue;
+                        }
+                        last_seen = stamp;
+
+                        if stamp.is_none() {
+                            tracing::warn!("Config file {:?} disappeared, keeping the current config", config_path);
+                            continue;
+                        }
+                        tracing::info!("Config file changed, reloading");
+                        if let Err(e) = reload_config(&config, &config_path) {
+                            tracing::error!("Failed to reload configuration, keeping the previous one: {:#}", e);
+                        }
+                    },
+                    _ = shutdown_rx.recv() => break,
+                }
+            }
+        });
+    }
+
+    async fn start_update_loops(&mut self) {
+        let config_rx = self.config.subscribe();
         let shutdown_tx = self.shutdown_tx.clone();
+        let heartbeats = self.heartbeats.clone();
+
+        // Nothing else locks the state for writing; collectors and commands
+        // alike send their updates to a single owner task
+        let state_updates = self.state_writes.clone();
+        if let Some(updates_rx) = self.state_writes_rx.take() {
+            spawn_state_owner(self.state.clone(), self.state_events.clone(), updates_rx);
+        }
 
         // System info update loop (reduced to every 10 seconds for memory efficiency)
         {
-            let state = state.clone();
-            let config = config.clone();
+            let config_rx = config_rx.clone();
+            let state_updates = state_updates.clone();
+            let heartbeats = heartbeats.clone();
             let mut collector = SystemCollector::new();
             let mut shutdown_rx = shutdown_tx.subscribe();
+            heartbeats.register(SYSTEM_LOOP, Duration::from_secs(10), true);
             
             tokio::spawn(async move {
                 let mut interval = tokio::time::interval(Duration::from_secs(10));
                 loop {
                     tokio::select! {
                         _ = interval.tick() => {
-                    
-                    if let Ok(mut state_guard) = state.try_write() {
-                        if let Ok(system_info) = collector.collect_system_info() {
-                            state_guard.system_info = system_info;
-                        }
-                        
-                        if let Ok(storage_info) = collector.collect_storage_info() {
-                            state_guard.storage_info = storage_info;
-                        }
-                        
-                        if let Ok(user_info) = collector.collect_user_info(&config) {
-                            state_guard.user_info = user_info;
-                        }
-                        
-                        if let Ok(toggles) = collector.collect_toggles() {
-                            state_guard.toggles = toggles;
-                        }
-                    }
+                            let started = Instant::now();
+                            let config = config_rx.borrow().clone();
+                            let updates;
+                            let sections = [StateSection::SystemInfo, StateSection::StorageInfo, StateSection::UserInfo, StateSection::Toggles];
+                            (collector, updates) = collect_blocking(collector, &sections, move |collector| {
+                                vec![
+                                    Collected::new(StateSection::SystemInfo, "sysinfo", collector.collect_system_info().map(StateUpdate::SystemInfo)),
+                                    Collected::new(StateSection::StorageInfo, "df", collector.collect_storage_info().map(StateUpdate::StorageInfo)),
+                                    Collected::new(StateSection::UserInfo, "config", collector.collect_user_info(&config).map(StateUpdate::UserInfo)),
+                                    Collected::new(StateSection::Toggles, "nmcli, bluetoothctl", collector.collect_toggles().map(StateUpdate::Toggles)),
+                                ]
+                            }).await;
+                            send_updates(&state_updates, updates).await;
+                            heartbeats.beat(SYSTEM_LOOP, started.elapsed());
                         },
                         _ = shutdown_rx.recv() => {
                             tracing::info!("System info collector shutting down");
@@ -179,25 +834,32 @@ impl VacuumDaemon {
 
         // Network update loop (reduced to every 5 seconds for memory efficiency)
         {
-            let state = state.clone();
+            let state_updates = state_updates.clone();
+            let heartbeats = heartbeats.clone();
             let mut collector = SystemCollector::new();
             let mut shutdown_rx = shutdown_tx.subscribe();
+            heartbeats.register(NETWORK_LOOP, Duration::from_secs(5), true);
             
             tokio::spawn(async move {
                 let mut interval = tokio::time::interval(Duration::from_secs(5));
                 loop {
                     tokio::select! {
                         _ = interval.tick() => {
-                            if let Ok(mut state_guard) = state.try_write() {
-                                if let Ok(network_status) = collector.collect_network_status() {
-                                    let interface = network_status.interface.clone();
-                                    state_guard.network_status = network_status;
-                                    
-                                    if let Ok(network_traffic) = collector.collect_network_traffic(&interface) {
-                                        state_guard.network_traffic = network_traffic;
-                                    }
-                                }
-                            }
+                            let started = Instant::now();
+                            let updates;
+                            (collector, updates) = collect_blocking(collector, &[StateSection::NetworkStatus, StateSection::NetworkTraffic], |collector| {
+                                let network_status = collector.collect_network_status();
+                                let Ok(interface) = network_status.as_ref().map(|status| status.interface.clone()) else {
+                                    // Traffic is per interface, so there's nothing to measure without one
+                                    return vec![Collected::new(StateSection::NetworkStatus, "ip", network_status.map(StateUpdate::NetworkStatus))];
+                                };
+                                vec![
+                                    Collected::new(StateSection::NetworkStatus, "ip", network_status.map(StateUpdate::NetworkStatus)),
+                                    Collected::new(StateSection::NetworkTraffic, "/proc/net/dev", collector.collect_network_traffic(&interface).map(StateUpdate::NetworkTraffic)),
+                                ]
+                            }).await;
+                            send_updates(&state_updates, updates).await;
+                            heartbeats.beat(NETWORK_LOOP, started.elapsed());
                         },
                         _ = shutdown_rx.recv() => {
                             tracing::info!("Network collector shutting down");
@@ -210,27 +872,29 @@ impl VacuumDaemon {
 
         // Audio update loop (reduced to every 3 seconds for memory efficiency)
         {
-            let state = state.clone();
-            let collector = SystemCollector::new();
+            let state_updates = state_updates.clone();
+            let heartbeats = heartbeats.clone();
+            let mut collector = SystemCollector::new();
             let mut shutdown_rx = shutdown_tx.subscribe();
+            heartbeats.register(AUDIO_LOOP, Duration::from_secs(3), true);
             
             tokio::spawn(async move {
                 let mut interval = tokio::time::interval(Duration::from_secs(3));
                 loop {
                     tokio::select! {
                         _ = interval.tick() => {
-                            if let Ok(mut state_guard) = state.try_write() {
-                                if let Ok(audio_status) = collector.collect_audio_status() {
-                                    state_guard.audio_status = audio_status;
-                                }
-                                
-                                if let Ok(volume_state) = collector.collect_volume_state() {
-                                    state_guard.volume_state = volume_state;
-                                }
-                                
+                            let started = Instant::now();
+                            let updates;
+                            (collector, updates) = collect_blocking(collector, &[StateSection::AudioStatus, StateSection::VolumeState], |collector| {
                                 // Only collect audio visualizer data when explicitly requested
                                 // This saves significant CPU/memory resources when GUI is not displaying visualizer
-                            }
+                                vec![
+                                    Collected::new(StateSection::AudioStatus, "playerctl", collector.collect_audio_status().map(StateUpdate::AudioStatus)),
+                                    Collected::new(StateSection::VolumeState, "pactl", collector.collect_volume_state().map(StateUpdate::VolumeState)),
+                                ]
+                            }).await;
+                            send_updates(&state_updates, updates).await;
+                            heartbeats.beat(AUDIO_LOOP, started.elapsed());
                         },
                         _ = shutdown_rx.recv() => {
                             tracing::info!("Audio collector shutting down");
@@ -241,27 +905,44 @@ impl VacuumDaemon {
             });
         }
 
-        // Weather update loop (every 15 minutes)
+        // Weather update loop (every 15 minutes by default; follows config changes)
         {
-            let state = state.clone();
-            let config = config.clone();
-            let collector = if let Some(ref api_key) = config.weather.api_key {
-                SystemCollector::with_weather_api_key(api_key.clone())
-            } else {
-                SystemCollector::new()
-            };
+            let mut config_rx = config_rx.clone();
+            let state_updates = state_updates.clone();
+            let mut config = config_rx.borrow_and_update().clone();
+            let mut collector = weather_collector(&config);
+            let heartbeats = heartbeats.clone();
             let mut shutdown_rx = shutdown_tx.subscribe();
+            // Weather depends on the network, so it doesn't hold up readiness
+            heartbeats.register(WEATHER_LOOP, weather_interval(&config), false);
             
             tokio::spawn(async move {
-                let mut interval = tokio::time::interval(Duration::from_secs(config.weather.update_interval_minutes as u64 * 60));
+                let mut interval = tokio::time::interval(weather_interval(&config));
                 loop {
                     tokio::select! {
                         _ = interval.tick() => {
-                            if let Ok(mut state_guard) = state.try_write() {
-                                if let Ok(weather_info) = collector.collect_weather_info(&config).await {
-                                    state_guard.weather_info = weather_info;
-                                }
+                            let started = Instant::now();
+                            let source = weather_source(&config);
+                            let weather_info = collector.collect_weather_info(&config).await;
+                            send_updates(&state_updates, vec![
+                                Collected::new(StateSection::WeatherInfo, source, weather_info.map(StateUpdate::WeatherInfo)),
+                            ]).await;
+                            heartbeats.beat(WEATHER_LOOP, started.elapsed());
+                        },
+                        Ok(()) = config_rx.changed() => {
+                            let new_config = config_rx.borrow_and_update().clone();
+                            if new_config.weather == config.weather {
+                                continue;
+                            }
+                            if new_config.weather.api_key != config.weather.api_key {
+                                collector = weather_collector(&new_config);
                             }
+                            config = new_config;
+
+                            // A fresh interval ticks immediately, so the new location shows up right away
+                            interval = tokio::time::interval(weather_interval(&config));
+                            heartbeats.register(WEATHER_LOOP, weather_interval(&config), false);
+                            tracing::info!("Weather collector picked up new configuration");
                         },
                         _ = shutdown_rx.recv() => {
                             tracing::info!("Weather collector shutting down");
@@ -272,84 +953,548 @@ impl VacuumDaemon {
             });
         }
 
-        // Initialize launcher shortcuts
+        // Launcher shortcuts come straight from config, so refresh them on every change
         {
-            let state = state.clone();
-            let config = config.clone();
-            
-            if let Ok(mut state_guard) = state.try_write() {
-                state_guard.launcher_shortcuts.left_links = config.shortcuts.left_links.iter()
-                    .map(|link| crate::state::LinkButton {
-                        label: link.label.clone(),
-                        url: link.url.clone(),
-                        icon_name: link.icon_name.clone(),
-                        icon_path: link.icon_path.clone(),
-                    })
-                    .collect();
-                state_guard.launcher_shortcuts.launcher_command = config.shortcuts.launcher_command.clone();
-            }
+            let mut config_rx = config_rx.clone();
+            let mut shutdown_rx = shutdown_tx.subscribe();
+
+            let shortcuts = launcher_shortcuts(&config_rx.borrow_and_update());
+            send_updates(&state_updates, vec![Collected::ok(StateUpdate::LauncherShortcuts(shortcuts), "config")]).await;
+
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        Ok(()) = config_rx.changed() => {
+                            let shortcuts = launcher_shortcuts(&config_rx.borrow_and_update());
+                            send_updates(&state_updates, vec![Collected::ok(StateUpdate::LauncherShortcuts(shortcuts), "config")]).await;
+                        },
+                        _ = shutdown_rx.recv() => break,
+                    }
+                }
+            });
         }
     }
 }
 
-async fn handle_ipc_connection(
-    mut stream: UnixStream,
+/// Outcome of refreshing one section, on its way to the state owner.
+struct Collected {
+    section: StateSection,
+    source: &'static str,
+    result: std::result::Result<StateUpdate, String>,
+}
+
+impl Collected {
+    fn new(section: StateSection, source: &'static str, result: Result<StateUpdate>) -> Self {
+        Self { section, source, result: result.map_err(|e| format!("{:#}", e)) }
+    }
+
+    fn ok(update: StateUpdate, source: &'static str) -> Self {
+        Self { section: update.section(), source, result: Ok(update) }
+    }
+}
+
+/// Collected updates waiting for the state owner. Collectors wait for room
+/// rather than dropping results when it falls behind.
+const STATE_UPDATE_BUFFER: usize = 64;
+/// How often a connection that stopped sending checks whether its client left.
+const HANGUP_POLL: Duration = Duration::from_millis(100);
+
+/// Applying a batch should take microseconds; anything slower is worth a warning.
+const SLOW_STATE_APPLY: Duration = Duration::from_millis(1);
+
+/// Derives a section from the current state, or `None` to leave it alone.
+type DeriveUpdate = Box<dyn FnOnce(&VacuumState) -> Option<StateUpdate> + Send>;
+
+/// A write for the state owner.
+enum StateWrite {
+    Collected(Collected),
+    /// A command's update; `applied` fires once it is stored and published.
+    Derived {
+        source: String,
+        derive: DeriveUpdate,
+        applied: oneshot::Sender<()>,
+    },
+}
+
+/// Has the state owner derive a section from the current state and publish it
+/// if it changed. Returns the rest of `derive`'s result once the write is
+/// applied, or `None` if the owner has stopped.
+async fn derive_update<T: Send + 'static>(
+    state_writes: &mpsc::Sender<StateWrite>,
+    source: &str,
+    derive: impl FnOnce(&VacuumState) -> (Option<StateUpdate>, T) + Send + 'static,
+) -> Option<T> {
+    let (value_tx, value_rx) = oneshot::channel();
+    let (applied, applied_rx) = oneshot::channel();
+    let derive = Box::new(move |state: &VacuumState| {
+        let (update, value) = derive(state);
+        let _ = value_tx.send(value);
+        update
+    });
+    state_writes.send(StateWrite::Derived { source: source.to_string(), derive, applied }).await.ok()?;
+    applied_rx.await.ok()?;
+    value_rx.await.ok()
+}
+
+/// Owns every write to the state: applies each batch of updates under a
+/// short write lock and publishes the sections that actually changed.
+/// Runs until every sender has been dropped.
+fn spawn_state_owner(
     state: Arc<RwLock<VacuumState>>,
-    config: Config,
-    actions: ActionHandler,
-) -> Result<()> {
-    let mut buffer = vec![0u8; 4096];
-    let n = stream.read(&mut buffer).await?;
-    
-    if n == 0 {
-        tracing::warn!("Received empty IPC message");
-        return Ok(());
-    }
-
-    // Validate message size
-    if n >= 4096 {
-        tracing::warn!("IPC message too large ({} bytes), rejecting", n);
-        let error_response = IpcResponse::Error("Message too large".to_string());
-        let error_data = serde_json::to_vec(&error_response)?;
-        stream.write_all(&error_data).await?;
-        return Ok(());
-    }
-
-    // Validate JSON and command structure
-    let command = match serde_json::from_slice::<IpcCommand>(&buffer[..n]) {
-        Ok(cmd) => cmd,
+    events: broadcast::Sender<StateUpdate>,
+    mut updates: mpsc::Receiver<StateWrite>,
+) {
+    tokio::spawn(async move {
+        let mut batch = Vec::new();
+        let mut applied = Vec::new();
+        while updates.recv_many(&mut batch, STATE_UPDATE_BUFFER).await > 0 {
+            let mut state_guard = state.write().await;
+            let started = Instant::now();
+            for write in batch.drain(..) {
+                let collected = match write {
+                    StateWrite::Collected(collected) => collected,
+                    StateWrite::Derived { source, derive, applied: done } => {
+                        if let Some(update) = derive(&state_guard) {
+                            state_guard.mark_updated(update.section(), &source);
+                            publish(&mut state_guard, &events, update);
+                        }
+                        applied.push(done);
+                        continue;
+                    }
+                };
+                match collected.result {
+                    Ok(update) => {
+                        state_guard.mark_updated(collected.section, collected.source);
+                        publish(&mut state_guard, &events, update);
+                    }
+                    Err(error) => {
+                        let previous = state_guard.sections.get(&collected.section)
+                            .and_then(|meta| meta.last_error.as_deref());
+                        // Only log when the error changes, not on every failing tick
+                        if previous != Some(error.as_str()) {
+                            tracing::warn!("Collecting {:?} failed: {}", collected.section, error);
+                        }
+                        state_guard.mark_failed(collected.section, collected.source, error);
+                    }
+                }
+            }
+            let held = started.elapsed();
+            drop(state_guard);
+            for done in applied.drain(..) {
+                let _ = done.send(());
+            }
+
+            if held > SLOW_STATE_APPLY {
+                tracing::warn!("Applying collected state held the lock for {:?}", held);
+            } else {
+                tracing::trace!("Applied collected state in {:?}", held);
+            }
+        }
+    });
+}
+
+/// Runs a collection pass on the blocking pool, since collectors shell out
+/// to tools like `lspci` and `nmcli`, and hands the collector back afterwards.
+/// A panic marks every one of `sections` as failed and starts over with a
+/// fresh collector, so the loop keeps running.
+async fn collect_blocking<F>(mut collector: SystemCollector, sections: &[StateSection], collect: F) -> (SystemCollector, Vec<Collected>)
+where
+    F: FnOnce(&mut SystemCollector) -> Vec<Collected> + Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(move || {
+        let updates = collect(&mut collector);
+        (collector, updates)
+    })
+    .await;
+
+    match result {
+        Ok(collected) => collected,
         Err(e) => {
-            tracing::warn!("Invalid IPC message format: {}", e);
-            let error_response = IpcResponse::Error(format!("Invalid message format: {}", e));
-            let error_data = serde_json::to_vec(&error_response)?;
-            stream.write_all(&error_data).await?;
-            return Ok(());
+            tracing::error!("Collector for {:?} panicked: {}", sections, e);
+            let failed = sections.iter()
+                .map(|section| Collected::new(*section, "collector", Err(anyhow::anyhow!("Collector panicked: {}", e))))
+                .collect();
+            (SystemCollector::new(), failed)
+        }
+    }
+}
+
+async fn send_updates(state_updates: &mpsc::Sender<StateWrite>, updates: Vec<Collected>) {
+    for update in updates {
+        if state_updates.send(StateWrite::Collected(update)).await.is_err() {
+            // The owner only stops once the daemon is shutting down
+            break;
+        }
+    }
+}
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Modification time and size of the config file, or `None` if it is missing.
+fn config_file_stamp(path: &std::path::Path) -> Option<(std::time::SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn weather_collector(config: &Config) -> SystemCollector {
+    if let Some(ref api_key) = config.weather.api_key {
+        SystemCollector::with_weather_api_key(api_key.clone())
+    } else {
+        SystemCollector::new()
+    }
+}
+
+/// Weather falls back to placeholder data without an API key.
+fn weather_source(config: &Config) -> &'static str {
+    if config.weather.api_key.is_some() { "openweathermap" } else { "fallback" }
+}
+
+fn weather_interval(config: &Config) -> Duration {
+    Duration::from_secs(config.weather.update_interval_minutes as u64 * 60)
+}
+
+fn launcher_shortcuts(config: &Config) -> LauncherShortcuts {
+    LauncherShortcuts {
+        left_links: config.shortcuts.left_links.iter()
+            .map(|link| LinkButton {
+                label: link.label.clone(),
+                url: link.url.clone(),
+                icon_name: link.icon_name.clone(),
+                icon_path: link.icon_path.clone(),
+            })
+            .collect(),
+        launcher_command: config.shortcuts.launcher_command.clone(),
+    }
+}
+
+/// Re-reads config.toml and swaps it in. On error the old config stays active.
+/// Returns whether anything changed.
+fn reload_config(config: &watch::Sender<Arc<Config>>, config_path: &std::path::Path) -> Result<bool> {
+    let new_config = load_config_from(config_path)?;
+    let changed = config.send_if_modified(|current| {
+        if **current == new_config {
+            return false;
+        }
+        *current = Arc::new(new_config);
+        true
+    });
+    if changed {
+        tracing::info!("Configuration reloaded");
+    }
+    Ok(changed)
+}
+
+/// Patches, saves and swaps in the config. Patches take `config_writes` in
+/// turn so concurrent ones can't overwrite each other, and the save runs on
+/// the blocking pool instead of under the config lock.
+async fn patch_config(
+    config: &watch::Sender<Arc<Config>>,
+    config_writes: &tokio::sync::Mutex<()>,
+    config_path: &std::path::Path,
+    patch: &serde_json::Value,
+) -> Result<Arc<Config>> {
+    let _patching = config_writes.lock().await;
+    let current = config.borrow().clone();
+    let patched = config::apply_config_patch(&current, patch)?;
+
+    let config_path = config_path.to_path_buf();
+    let patched = tokio::task::spawn_blocking(move || config::save_config_to(&config_path, &patched).map(|_| patched))
+        .await
+        .context("Config save panicked")??;
+
+    if patched == *current {
+        return Ok(current);
+    }
+    let patched = Arc::new(patched);
+    config.send_replace(patched.clone());
+    Ok(patched)
+}
+
+async fn handle_ipc_connection(stream: UnixStream, context: IpcContext) -> Result<()> {
+    let max_len = context.config.borrow().ipc.max_message_bytes;
+    let (mut reader, mut writer) = stream.into_split();
+    let (reply_tx, mut reply_rx) = mpsc::channel::<IpcReply>(32);
+    let mut subscriptions = Vec::new();
+    let mut overlay_frontends = Vec::new();
+    let mut in_flight = tokio::task::JoinSet::new();
+
+    // A single writer task serializes replies, so requests can complete in any order
+    let mut writer_task = tokio::spawn(async move {
+        while let Some(reply) = reply_rx.recv().await {
+            ipc::write_message(&mut writer, &reply, max_len).await?;
+        }
+        Ok::<(), anyhow::Error>(())
+    });
+
+    let end_of_requests = loop {
+        let payload = match ipc::read_frame(&mut reader, max_len).await {
+            Ok(Some(payload)) => payload,
+            Ok(None) => break true,
+            Err(e) => {
+                // The stream can't be resynchronized after a bad frame, so reply and hang up
+                tracing::warn!("Rejecting IPC message: {}", e);
+                let _ = reply_tx.send(IpcReply { id: 0, response: IpcResponse::Error(e.to_string()) }).await;
+                break false;
+            }
+        };
+
+        // Validate JSON and command structure
+        let request = match serde_json::from_slice::<IpcRequest>(&payload) {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::warn!("Invalid IPC message format: {}", e);
+                let id = serde_json::from_slice::<serde_json::Value>(&payload)
+                    .ok()
+                    .and_then(|value| value.get("id")?.as_u64())
+                    .unwrap_or(0);
+                let response = IpcResponse::Error(format!("Invalid message format: {}", e));
+                let _ = reply_tx.send(IpcReply { id, response }).await;
+                continue;
+            }
+        };
+
+        if !context.policy.allows(context.peer, &request.command) {
+            tracing::warn!("Denied {} for a group peer", request.command.name());
+            let response = IpcResponse::Error(format!(
+                "Permission denied: {} is restricted to the daemon owner", request.command.name()
+            ));
+            context.audit(&request.command, &response).await;
+            let _ = reply_tx.send(IpcReply { id: request.id, response }).await;
+            continue;
+        }
+
+        // Validate command-specific requirements
+        if let Err(validation_error) = validate_command(&request.command) {
+            tracing::warn!("IPC command validation failed: {}", validation_error);
+            let response = IpcResponse::Error(validation_error);
+            context.audit(&request.command, &response).await;
+            let _ = reply_tx.send(IpcReply { id: request.id, response }).await;
+            continue;
+        }
+
+        if let IpcCommand::Subscribe { sections } = request.command {
+            subscriptions.push(spawn_subscription(request.id, sections, &context, reply_tx.clone()).await);
+            continue;
+        }
+
+        if let IpcCommand::RegisterOverlay { name } = request.command {
+            tracing::info!("Overlay frontend '{}' registered", name);
+            let frontend = name.clone();
+            context.update_overlay_frontends(|frontends| frontends.push(frontend)).await;
+            overlay_frontends.push(name);
+            subscriptions.push(spawn_subscription(request.id, vec![StateSection::Overlay], &context, reply_tx.clone()).await);
+            continue;
         }
+
+        // Forget requests that already finished so the set only holds live ones
+        while in_flight.try_join_next().is_some() {}
+
+        let context = context.clone();
+        let reply_tx = reply_tx.clone();
+        in_flight.spawn(async move {
+            let audit = context.audit_guard(&request.command);
+            let response = handle_command(request.command, &context).await;
+            audit.finish(&response).await;
+            let _ = reply_tx.send(IpcReply { id: request.id, response }).await;
+        });
     };
 
-    // Validate command-specific requirements
-    if let Err(validation_error) = validate_command(&command) {
-        tracing::warn!("IPC command validation failed: {}", validation_error);
-        let error_response = IpcResponse::Error(validation_error);
-        let error_data = serde_json::to_vec(&error_response)?;
-        stream.write_all(&error_data).await?;
-        return Ok(());
+    // A client that only shut down its write half still gets its replies,
+    // so cancel only once it hangs up entirely or a reply can't be delivered
+    let mut writer_result = None;
+    if end_of_requests {
+        loop {
+            tokio::select! {
+                finished = in_flight.join_next() => {
+                    if finished.is_none() {
+                        break;
+                    }
+                }
+                result = &mut writer_task => {
+                    writer_result = Some(result);
+                    break;
+                }
+                _ = hung_up(&reader) => break,
+            }
+        }
+    }
+    // Dropping an action kills its child process
+    in_flight.shutdown().await;
+
+    // Subscriptions only end with the connection; stop them so the writer can drain
+    for subscription in subscriptions {
+        subscription.abort();
+    }
+
+    for name in overlay_frontends {
+        tracing::info!("Overlay frontend '{}' disconnected", name);
+        context.update_overlay_frontends(move |frontends| {
+            if let Some(index) = frontends.iter().position(|frontend| *frontend == name) {
+                frontends.remove(index);
+            }
+        }).await;
     }
 
-    let response = handle_command(command, state, config, actions).await;
-    
-    let response_data = serde_json::to_vec(&response)?;
-    stream.write_all(&response_data).await?;
-    
+    // Flush replies that were already queued before closing the write half
+    drop(reply_tx);
+    let written = match writer_result {
+        Some(result) => result,
+        None => writer_task.await,
+    };
+    written??;
+
     Ok(())
 }
 
+/// Resolves once the peer has closed its end of the socket for good. Unlike
+/// a read EOF, a half-close with `shutdown(SHUT_WR)` doesn't count.
+async fn hung_up(reader: &tokio::net::unix::OwnedReadHalf) {
+    loop {
+        match reader.ready(tokio::io::Interest::WRITABLE).await {
+            Ok(ready) if !ready.is_write_closed() => tokio::time::sleep(HANGUP_POLL).await,
+            _ => return,
+        }
+    }
+}
+
+/// Registers on the session bus and forwards state changes as signals.
+async fn start_dbus_service(context: &IpcContext) -> Result<(zbus::Connection, tokio::task::JoinHandle<()>)> {
+    let dispatch_context = context.clone();
+    let dispatch: dbus::Dispatch = Arc::new(move |command, caller| {
+        let context = IpcContext { caller, ..dispatch_context.clone() };
+        Box::pin(async move { dispatch_command(command, &context).await })
+    });
+    let connection = dbus::serve(context.state.clone(), dispatch).await?;
+    tracing::info!("Serving {} on the session bus", dbus::bus_name());
+
+    let events = context.state_events.subscribe();
+    let signal_connection = connection.clone();
+    let forwarder = tokio::spawn(async move {
+        if let Err(e) = dbus::forward_changes(&signal_connection, events).await {
+            tracing::error!("Stopped emitting D-Bus signals: {:#}", e);
+        }
+    });
+    Ok((connection, forwarder))
+}
+
+/// Runs a command that didn't arrive on the socket, with the same validation
+/// and audit trail as one that did.
+async fn dispatch_command(command: IpcCommand, context: &IpcContext) -> IpcResponse {
+    let audit = context.audit_guard(&command);
+    let response = match validate_command(&command) {
+        Ok(()) => handle_command(command, context).await,
+        Err(e) => IpcResponse::Error(e),
+    };
+    audit.finish(&response).await;
+    response
+}
+
+fn schedule_power_action(context: &IpcContext, action: PowerAction, when: &ScheduleTime) -> IpcResponse {
+    match context.schedule.schedule(action, when) {
+        Ok(scheduled) => {
+            tracing::info!("{:?} scheduled in {} seconds", action, scheduled.remaining_secs);
+            IpcResponse::Success
+        }
+        Err(e) => IpcResponse::Error(format!("{:#}", e)),
+    }
+}
+
+async fn run_power_action(command: &IpcCommand, config: &Config, actions: &ActionHandler) -> IpcResponse {
+    let name = command.name();
+    let result = match command {
+        IpcCommand::Logout => run_action(name, config, actions.logout()).await,
+        IpcCommand::Reboot => run_action(name, config, actions.reboot()).await,
+        IpcCommand::Shutdown => run_action(name, config, actions.shutdown()).await,
+        _ => return IpcResponse::Error(format!("{} is not a power action", name)),
+    };
+    match result {
+        Ok(_) => IpcResponse::Success,
+        Err(response) => response,
+    }
+}
+
+/// Runs an action under its configured timeout. Timing out drops the action,
+/// which kills whatever process it was waiting on.
+async fn run_action<T>(
+    command: &str,
+    config: &Config,
+    action: impl std::future::Future<Output = Result<T>>,
+) -> std::result::Result<T, IpcResponse> {
+    let timeout = config.actions.timeout_for(command);
+    match tokio::time::timeout(timeout, action).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(IpcResponse::Error(e.to_string())),
+        Err(_) => {
+            tracing::warn!("{} timed out after {:?}, killed it", command, timeout);
+            Err(IpcResponse::ActionTimedOut {
+                command: command.to_string(),
+                timeout_ms: timeout.as_millis() as u64,
+            })
+        }
+    }
+}
+
+/// Acknowledges a subscription, sends the current value of each requested
+/// section, then forwards every change under the subscription's request ID.
+async fn spawn_subscription(
+    id: u64,
+    sections: Vec<StateSection>,
+    context: &IpcContext,
+    reply_tx: mpsc::Sender<IpcReply>,
+) -> tokio::task::JoinHandle<()> {
+    let sections = if sections.is_empty() { StateSection::ALL.to_vec() } else { sections };
+
+    // Subscribe before taking the snapshot so no change can slip in between
+    let mut events = context.state_events.subscribe();
+    let snapshot: Vec<StateUpdate> = {
+        let state_guard = context.state.read().await;
+        sections.iter().map(|section| state_guard.section(*section)).collect()
+    };
+    let state = context.state.clone();
+
+    tokio::spawn(async move {
+        if reply_tx.send(IpcReply { id, response: IpcResponse::Success }).await.is_err() {
+            return;
+        }
+        for update in snapshot {
+            if reply_tx.send(IpcReply { id, response: IpcResponse::StateChanged(update) }).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            match events.recv().await {
+                Ok(update) => {
+                    if !sections.contains(&update.section()) {
+                        continue;
+                    }
+                    if reply_tx.send(IpcReply { id, response: IpcResponse::StateChanged(update) }).await.is_err() {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("State subscriber {} fell behind, skipped {} updates", id, skipped);
+                    // The skipped changes are gone, so catch up from the current state
+                    let current: Vec<StateUpdate> = {
+                        let state_guard = state.read().await;
+                        sections.iter().map(|section| state_guard.section(*section)).collect()
+                    };
+                    for update in current {
+                        if reply_tx.send(IpcReply { id, response: IpcResponse::StateChanged(update) }).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    })
+}
+
 fn validate_command(command: &IpcCommand) -> Result<(), String> {
     match command {
-        IpcCommand::SetVolume(volume) => {
-            if *volume > 100 {
-                return Err("Volume must be between 0 and 100".to_string());
-            }
+        IpcCommand::SetVolume(volume) if *volume > 100 => {
+            return Err("Volume must be between 0 and 100".to_string());
         }
         IpcCommand::LaunchUrl(url) => {
             if url.is_empty() {
@@ -365,21 +1510,39 @@ fn validate_command(command: &IpcCommand) -> Result<(), String> {
     Ok(())
 }
 
-async fn handle_command(
-    command: IpcCommand,
-    state: Arc<RwLock<VacuumState>>,
-    config: Config,
-    actions: ActionHandler,
-) -> IpcResponse {
+async fn handle_command(command: IpcCommand, context: &IpcContext) -> IpcResponse {
+    let actions = &context.actions;
+    let config = context.config.borrow().clone();
+    let command_name = command.name().to_string();
+
     match command {
+        IpcCommand::Hello { protocol_version, client_name } => {
+            if protocol_version != ipc::PROTOCOL_VERSION {
+                tracing::info!(
+                    "Client '{}' speaks protocol v{}, daemon speaks v{}",
+                    client_name, protocol_version, ipc::PROTOCOL_VERSION
+                );
+            } else {
+                tracing::debug!("Client '{}' connected", client_name);
+            }
+            IpcResponse::Hello(daemon_info(&config))
+        }
         IpcCommand::ToggleOverlay => {
-            // For now, just return success - GUI will handle overlay display
-            IpcResponse::Success
+            IpcResponse::ToggleResult(context.set_overlay_visible(&command_name, |visible| !visible).await)
+        }
+        IpcCommand::ShowOverlay => {
+            IpcResponse::ToggleResult(context.set_overlay_visible(&command_name, |_| true).await)
+        }
+        IpcCommand::HideOverlay => {
+            IpcResponse::ToggleResult(context.set_overlay_visible(&command_name, |_| false).await)
+        }
+        IpcCommand::RegisterOverlay { .. } => {
+            // Intercepted by the connection loop, which tracks registrations per connection
+            IpcResponse::Error("RegisterOverlay is only available on an IPC connection".to_string())
         }
         IpcCommand::GetState => {
-            match state.read().await {
-                state_guard => IpcResponse::State(state_guard.clone()),
-            }
+            let state_guard = context.state.read().await;
+            IpcResponse::State(Box::new(state_guard.clone()))
         }
         IpcCommand::GetAudioVisualizer => {
             let collector = SystemCollector::new();
@@ -389,52 +1552,130 @@ async fn handle_command(
             }
         }
         IpcCommand::SetVolume(volume) => {
-            match actions.set_volume(volume) {
-                Ok(_) => IpcResponse::Success,
-                Err(e) => IpcResponse::Error(e.to_string()),
+            match run_action(&command_name, &config, actions.set_volume(volume)).await {
+                Ok(_) => {
+                    context.update_state(&command_name, move |state| StateUpdate::VolumeState(VolumeState {
+                        level_percent: volume,
+                        ..state.volume_state.clone()
+                    })).await;
+                    IpcResponse::Success
+                }
+                Err(response) => response,
             }
         }
         IpcCommand::ToggleMute => {
-            match actions.toggle_mute() {
-                Ok(muted) => IpcResponse::ToggleResult(muted),
-                Err(e) => IpcResponse::Error(e.to_string()),
+            match run_action(&command_name, &config, actions.toggle_mute()).await {
+                Ok(muted) => {
+                    context.update_state(&command_name, move |state| StateUpdate::VolumeState(VolumeState {
+                        muted,
+                        ..state.volume_state.clone()
+                    })).await;
+                    IpcResponse::ToggleResult(muted)
+                }
+                Err(response) => response,
             }
         }
         IpcCommand::ToggleWifi => {
-            match actions.toggle_wifi() {
-                Ok(enabled) => IpcResponse::ToggleResult(enabled),
-                Err(e) => IpcResponse::Error(e.to_string()),
+            match run_action(&command_name, &config, actions.toggle_wifi()).await {
+                Ok(enabled) => {
+                    context.update_state(&command_name, move |state| StateUpdate::Toggles(Toggles {
+                        wifi_enabled: enabled,
+                        ..state.toggles.clone()
+                    })).await;
+                    IpcResponse::ToggleResult(enabled)
+                }
+                Err(response) => response,
             }
         }
         IpcCommand::ToggleBluetooth => {
-            match actions.toggle_bluetooth() {
-                Ok(enabled) => IpcResponse::ToggleResult(enabled),
-                Err(e) => IpcResponse::Error(e.to_string()),
+            match run_action(&command_name, &config, actions.toggle_bluetooth()).await {
+                Ok(enabled) => {
+                    context.update_state(&command_name, move |state| StateUpdate::Toggles(Toggles {
+                        bluetooth_enabled: enabled,
+                        ..state.toggles.clone()
+                    })).await;
+                    IpcResponse::ToggleResult(enabled)
+                }
+                Err(response) => response,
             }
         }
         IpcCommand::ToggleVpn => {
-            let vpn_name = config.network.vpn_name.unwrap_or_else(|| "vpn".to_string());
-            match actions.toggle_vpn(&vpn_name) {
-                Ok(connected) => IpcResponse::ToggleResult(connected),
-                Err(e) => IpcResponse::Error(e.to_string()),
+            let vpn_name = config.network.vpn_name.clone().unwrap_or_else(|| "vpn".to_string());
+            match run_action(&command_name, &config, actions.toggle_vpn(&vpn_name)).await {
+                Ok(connected) => {
+                    context.update_state(&command_name, move |state| StateUpdate::Toggles(Toggles {
+                        vpn_connected: connected,
+                        ..state.toggles.clone()
+                    })).await;
+                    IpcResponse::ToggleResult(connected)
+                }
+                Err(response) => response,
             }
         }
-        IpcCommand::Logout => {
-            match actions.logout() {
-                Ok(_) => IpcResponse::Success,
+        IpcCommand::Logout | IpcCommand::Reboot | IpcCommand::Shutdown => {
+            if !config.actions.confirm_power_actions {
+                return run_power_action(&command, &config, actions).await;
+            }
+
+            let window = Duration::from_secs(config.actions.confirmation_window_secs);
+            match context.confirmations.request(command, window) {
+                Ok(token) => {
+                    tracing::info!("{} is waiting for confirmation", command_name);
+                    IpcResponse::ConfirmationRequired {
+                        token,
+                        command: command_name,
+                        expires_in_ms: window.as_millis() as u64,
+                    }
+                }
                 Err(e) => IpcResponse::Error(e.to_string()),
             }
         }
-        IpcCommand::Reboot => {
-            match actions.reboot() {
-                Ok(_) => IpcResponse::Success,
-                Err(e) => IpcResponse::Error(e.to_string()),
+        IpcCommand::ConfirmAction(token) => {
+            match context.confirmations.take(&token) {
+                Some(command) if !context.policy.allows(context.peer, &command) => {
+                    IpcResponse::Error(format!("Permission denied: {} is restricted to the daemon owner", command.name()))
+                }
+                Some(command) => {
+                    tracing::info!("{} confirmed", command.name());
+                    run_power_action(&command, &config, actions).await
+                }
+                None => IpcResponse::Error("Unknown or expired confirmation token".to_string()),
             }
         }
-        IpcCommand::Shutdown => {
-            match actions.shutdown() {
-                Ok(_) => IpcResponse::Success,
-                Err(e) => IpcResponse::Error(e.to_string()),
+        IpcCommand::ScheduleShutdown(when) => schedule_power_action(context, PowerAction::Shutdown, &when),
+        IpcCommand::ScheduleReboot(when) => schedule_power_action(context, PowerAction::Reboot, &when),
+        IpcCommand::Status => IpcResponse::Status(DaemonStatus {
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            pid: std::process::id(),
+            uptime_secs: context.started.elapsed().as_secs(),
+            socket_path: get_socket_path(),
+            config_path: context.config_path.clone(),
+            loops: context.heartbeats.loops(),
+            clients: context.clients.load(Ordering::Relaxed),
+            cava: if cfg!(cava_enabled) { "cava_enabled" } else { "cava_disabled" }.to_string(),
+        }),
+        IpcCommand::StopDaemon => {
+            tracing::info!("Stopping on request");
+            let shutdown = context.shutdown.clone();
+            tokio::spawn(async move {
+                // Give the reply a moment to go out before the listener closes
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let _ = shutdown.send(());
+            });
+            IpcResponse::Success
+        }
+        IpcCommand::CancelScheduled => match context.schedule.cancel() {
+            Some(scheduled) => {
+                tracing::info!("Cancelled scheduled {:?}", scheduled.action);
+                IpcResponse::Success
+            }
+            None => IpcResponse::Error("No power action is scheduled".to_string()),
+        },
+        IpcCommand::CancelAction(token) => {
+            if context.confirmations.cancel(&token) {
+                IpcResponse::Success
+            } else {
+                IpcResponse::Error("Unknown or expired confirmation token".to_string())
             }
         }
         IpcCommand::LaunchApp => {
@@ -449,41 +1690,411 @@ async fn handle_command(
                 Err(e) => IpcResponse::Error(e.to_string()),
             }
         }
+        IpcCommand::GetHealth => {
+            let state_guard = context.state.read().await;
+            IpcResponse::Health(health::health_report(&state_guard, &context.heartbeats))
+        }
+        IpcCommand::Subscribe { .. } => {
+            // Intercepted by the connection loop, which owns the reply stream
+            IpcResponse::Error("Subscribe is only available on an IPC connection".to_string())
+        }
+        IpcCommand::GetConfig => {
+            match config::redacted_config_json(&config) {
+                Ok(value) => IpcResponse::Config(value),
+                Err(e) => IpcResponse::Error(e.to_string()),
+            }
+        }
+        IpcCommand::PatchConfig(patch) => {
+            match patch_config(&context.config, &context.config_writes, &context.config_path, &patch).await {
+                Ok(config) => {
+                    match config::redacted_config_json(&config) {
+                        Ok(value) => IpcResponse::Config(value),
+                        Err(e) => IpcResponse::Error(e.to_string()),
+                    }
+                }
+                Err(e) => IpcResponse::Error(format!("{:#}", e)),
+            }
+        }
+        IpcCommand::ReloadConfig => {
+            match reload_config(&context.config, &context.config_path) {
+                Ok(_) => IpcResponse::Success,
+                Err(e) => IpcResponse::Error(format!("{:#}", e)),
+            }
+        }
+    }
+}
+
+/// Binds the daemon socket ourselves, replacing a stale one left by a dead daemon.
+async fn bind_socket(socket_path: &std::path::Path, group: Option<nix::unistd::Gid>) -> Result<UnixListener> {
+    // We hold the instance lock, so a socket left here belongs to a dead daemon
+    if socket_path.exists() {
+        tracing::info!("Removing stale socket file");
+        std::fs::remove_file(socket_path)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)
+        .with_context(|| format!("Failed to bind socket at {:?}", socket_path))?;
+    auth::restrict_socket(socket_path, group)?;
+    Ok(listener)
+}
+
+/// Private per-user directory holding the socket and PID file. Named
+/// instances get their own next to the default one.
+pub fn get_runtime_dir() -> PathBuf {
+    let dir_name = match &instance::current().name {
+        Some(name) => format!("vacuum-launcher-{}", name),
+        None => "vacuum-launcher".to_string(),
+    };
+    match dirs::runtime_dir().or_else(dirs::cache_dir) {
+        Some(mut path) => {
+            path.push(dir_name);
+            path
+        }
+        // /tmp is shared, so include the uid to keep users apart
+        None => PathBuf::from(format!("/tmp/{}-{}", dir_name, nix::unistd::Uid::current())),
     }
 }
 
 pub fn get_socket_path() -> PathBuf {
-    let mut path = dirs::runtime_dir()
-        .or_else(|| dirs::cache_dir())
-        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    if let Some(socket) = &instance::current().socket {
+        return socket.clone();
+    }
+    let mut path = get_runtime_dir();
     path.push("vacuum-launcher.sock");
     path
 }
 
+/// Next to an explicit socket, so daemons on different sockets don't share a lock.
 pub fn get_pid_path() -> PathBuf {
-    let mut path = dirs::runtime_dir()
-        .or_else(|| dirs::cache_dir())
-        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    if let Some(socket) = &instance::current().socket {
+        return socket.with_extension("pid");
+    }
+    let mut path = get_runtime_dir();
     path.push("vacuum-launcher.pid");
     path
 }
 
+pub fn get_schedule_path() -> PathBuf {
+    if let Some(socket) = &instance::current().socket {
+        return socket.with_extension("schedule.json");
+    }
+    let mut path = get_runtime_dir();
+    path.push("schedule.json");
+    path
+}
+
 pub async fn send_ipc_command(command: IpcCommand) -> Result<IpcResponse> {
-    let socket_path = get_socket_path();
-    let mut stream = UnixStream::connect(&socket_path)
-        .await
-        .context("Failed to connect to daemon")?;
+    IpcClient::connect().await?.request(command).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_context() -> IpcContext {
+        let (state_events, _) = broadcast::channel(64);
+        // Keep unit tests out of the real audit log
+        let mut config = Config::default();
+        config.audit.enabled = false;
+        let state = Arc::new(RwLock::new(VacuumState::default()));
+        let (state_writes, updates_rx) = mpsc::channel(STATE_UPDATE_BUFFER);
+        spawn_state_owner(state.clone(), state_events.clone(), updates_rx);
+        IpcContext {
+            state,
+            state_writes,
+            config: watch::channel(Arc::new(config)).0,
+            config_path: std::env::temp_dir().join(format!("vacuum-daemon-test-{}.toml", std::process::id())),
+            config_writes: Arc::new(tokio::sync::Mutex::new(())),
+            actions: ActionHandler::new(),
+            state_events,
+            policy: Arc::new(PeerPolicy::from_config(&Default::default())),
+            peer: PeerRole::Owner,
+            caller: Caller::daemon(),
+            heartbeats: LoopHeartbeats::new(),
+            confirmations: PendingConfirmations::new(),
+            schedule: PowerSchedule::load(std::env::temp_dir().join(format!("vacuum-daemon-test-schedule-{}.json", std::process::id()))),
+            audit: AuditLog::new(),
+            started: Instant::now(),
+            clients: Arc::new(AtomicUsize::new(0)),
+            shutdown: broadcast::channel(1).0,
+        }
+    }
+
+    fn spawn_test_connection_with(context: IpcContext) -> UnixStream {
+        let (client_stream, server_stream) = UnixStream::pair().unwrap();
+        tokio::spawn(handle_ipc_connection(server_stream, context));
+        client_stream
+    }
+
+    fn spawn_test_connection() -> UnixStream {
+        spawn_test_connection_with(test_context())
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_requests_share_one_connection() {
+        let client = IpcClient::from_stream(spawn_test_connection(), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        let (state, toggle, volume) = tokio::join!(
+            client.request(IpcCommand::GetState),
+            client.request(IpcCommand::ToggleOverlay),
+            client.request(IpcCommand::SetVolume(150)),
+        );
+
+        assert!(matches!(state.unwrap(), IpcResponse::State(_)));
+        assert!(matches!(toggle.unwrap(), IpcResponse::ToggleResult(true)));
+        assert!(matches!(volume.unwrap(), IpcResponse::Error(_)));
+
+        // The connection stays usable after the batch
+        assert!(matches!(client.request(IpcCommand::GetState).await.unwrap(), IpcResponse::State(_)));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_echoes_id() {
+        let mut stream = spawn_test_connection();
+        let max_len = ipc::DEFAULT_MAX_MESSAGE_BYTES;
+
+        ipc::write_frame(&mut stream, br#"{"id":7,"command":"NoSuchCommand"}"#, max_len).await.unwrap();
+        let reply: IpcReply = ipc::read_message(&mut stream, max_len).await.unwrap().unwrap();
+
+        assert_eq!(reply.id, 7);
+        assert!(matches!(reply.response, IpcResponse::Error(_)));
+    }
 
-    let command_data = serde_json::to_vec(&command)?;
-    stream.write_all(&command_data).await?;
+    #[tokio::test]
+    async fn test_half_closed_client_still_gets_replies() {
+        let mut stream = spawn_test_connection();
+        let max_len = ipc::DEFAULT_MAX_MESSAGE_BYTES;
 
-    let mut buffer = vec![0u8; 8192];
-    let n = stream.read(&mut buffer).await?;
-    
-    if n == 0 {
-        return Err(anyhow::anyhow!("No response from daemon"));
+        let request = IpcRequest { id: 3, command: IpcCommand::GetState };
+        ipc::write_message(&mut stream, &request, max_len).await.unwrap();
+        tokio::io::AsyncWriteExt::shutdown(&mut stream).await.unwrap();
+        let reply: IpcReply = ipc::read_message(&mut stream, max_len).await.unwrap().unwrap();
+
+        assert_eq!(reply.id, 3);
+        assert!(matches!(reply.response, IpcResponse::State(_)));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_pushes_matching_changes() {
+        let context = test_context();
+        let client = IpcClient::from_stream(spawn_test_connection_with(context.clone()), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        let mut updates = client.subscribe(vec![StateSection::VolumeState]).await.unwrap();
+
+        // The current value arrives first
+        let initial = updates.recv().await.unwrap();
+        assert_eq!(initial, StateUpdate::VolumeState(VolumeState::default()));
+
+        // Sections that weren't requested and unchanged values are not pushed
+        context.update_state("test", |_| StateUpdate::Toggles(Toggles { wifi_enabled: true, ..Toggles::default() })).await;
+        context.update_state("test", |state| StateUpdate::VolumeState(state.volume_state.clone())).await;
+        context.update_state("test", |_| StateUpdate::VolumeState(VolumeState { level_percent: 80, muted: true })).await;
+
+        let changed = updates.recv().await.unwrap();
+        assert_eq!(changed, StateUpdate::VolumeState(VolumeState { level_percent: 80, muted: true }));
+
+        // Regular requests still work alongside the subscription
+        assert!(matches!(client.request(IpcCommand::GetState).await.unwrap(), IpcResponse::State(_)));
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscription_catches_up_from_state() {
+        let context = test_context();
+        let client = IpcClient::from_stream(spawn_test_connection_with(context.clone()), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+        let mut updates = client.subscribe(vec![StateSection::VolumeState]).await.unwrap();
+        updates.recv().await.unwrap();
+
+        // Bury the volume change under more events than the channel holds
+        {
+            let mut state_guard = context.state.write().await;
+            let volume = StateUpdate::VolumeState(VolumeState { level_percent: 42, muted: false });
+            publish(&mut state_guard, &context.state_events, volume);
+            for i in 0..200 {
+                let toggles = Toggles { wifi_enabled: i % 2 == 0, ..Toggles::default() };
+                publish(&mut state_guard, &context.state_events, StateUpdate::Toggles(toggles));
+            }
+        }
+
+        let caught_up = tokio::time::timeout(Duration::from_secs(5), updates.recv()).await.unwrap().unwrap();
+        assert_eq!(caught_up, StateUpdate::VolumeState(VolumeState { level_percent: 42, muted: false }));
+    }
+
+    #[tokio::test]
+    async fn test_hello_reports_capabilities() {
+        let client = IpcClient::from_stream(spawn_test_connection(), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        let info = client.hello("test-client").await.unwrap();
+
+        assert_eq!(info.protocol_version, ipc::PROTOCOL_VERSION);
+        assert_eq!(info.daemon_version, env!("CARGO_PKG_VERSION"));
+        assert!(info.commands.iter().any(|name| name == "Subscribe"));
+        assert_eq!(info.visualizer, crate::cava::BACKEND);
+        assert!(info.collectors.contains(&StateSection::SystemInfo));
+    }
+
+    #[tokio::test]
+    async fn test_group_peer_cannot_run_owner_commands() {
+        let context = IpcContext { peer: PeerRole::GroupMember, ..test_context() };
+        let client = IpcClient::from_stream(spawn_test_connection_with(context), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        assert!(matches!(client.request(IpcCommand::GetState).await.unwrap(), IpcResponse::State(_)));
+        assert!(matches!(client.request(IpcCommand::Reboot).await.unwrap(), IpcResponse::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_patch_config_saves_and_applies() {
+        let context = test_context();
+        let config_path = context.config_path.clone();
+        let client = IpcClient::from_stream(spawn_test_connection_with(context.clone()), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        let patch = serde_json::json!({ "network": { "vpn_name": "office" } });
+        let response = client.request(IpcCommand::PatchConfig(patch)).await.unwrap();
+        let saved = load_config_from(&config_path);
+        let _ = std::fs::remove_file(&config_path);
+
+        match response {
+            IpcResponse::Config(value) => {
+                assert_eq!(value["network"]["vpn_name"], "office");
+                assert_eq!(value["weather"]["api_key"], config::REDACTED);
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+        assert_eq!(context.config.borrow().network.vpn_name.as_deref(), Some("office"));
+        assert_eq!(saved.unwrap().network.vpn_name.as_deref(), Some("office"));
+
+        // Concurrent patches apply one after the other instead of overwriting each other
+        let vpn = serde_json::json!({ "network": { "vpn_name": "home" } });
+        let interval = serde_json::json!({ "weather": { "update_interval_minutes": 30 } });
+        let (first, second) = tokio::join!(
+            patch_config(&context.config, &context.config_writes, &config_path, &vpn),
+            patch_config(&context.config, &context.config_writes, &config_path, &interval),
+        );
+        let _ = std::fs::remove_file(&config_path);
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(context.config.borrow().network.vpn_name.as_deref(), Some("home"));
+        assert_eq!(context.config.borrow().weather.update_interval_minutes, 30);
+
+        // Invalid patches are rejected and leave the config alone
+        let patch = serde_json::json!({ "weather": { "update_interval_minutes": "soon" } });
+        let response = client.request(IpcCommand::PatchConfig(patch)).await.unwrap();
+        assert!(matches!(response, IpcResponse::Error(_)));
+        assert_eq!(context.config.borrow().weather.update_interval_minutes, 30);
     }
 
-    let response: IpcResponse = serde_json::from_slice(&buffer[..n])?;
-    Ok(response)
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_overlay_frontends_follow_visibility() {
+        let context = test_context();
+        let frontend = IpcClient::from_stream(spawn_test_connection_with(context.clone()), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+        let hotkey = IpcClient::from_stream(spawn_test_connection_with(context.clone()), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        let mut updates = frontend.register_overlay("test-overlay").await.unwrap();
+        let StateUpdate::Overlay(initial) = updates.recv().await.unwrap() else { panic!("expected overlay state") };
+        assert!(!initial.visible);
+        assert_eq!(initial.frontends, vec!["test-overlay".to_string()]);
+
+        assert!(matches!(hotkey.request(IpcCommand::ToggleOverlay).await.unwrap(), IpcResponse::ToggleResult(true)));
+        let StateUpdate::Overlay(shown) = updates.recv().await.unwrap() else { panic!("expected overlay state") };
+        assert!(shown.visible);
+        assert!(shown.changed_at_ms > 0);
+
+        // Showing an already visible overlay changes nothing
+        assert!(matches!(hotkey.request(IpcCommand::ShowOverlay).await.unwrap(), IpcResponse::ToggleResult(true)));
+        assert!(matches!(hotkey.request(IpcCommand::HideOverlay).await.unwrap(), IpcResponse::ToggleResult(false)));
+        let StateUpdate::Overlay(hidden) = updates.recv().await.unwrap() else { panic!("expected overlay state") };
+        assert!(!hidden.visible);
+
+        drop(updates);
+        drop(frontend);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !context.state.read().await.overlay.frontends.is_empty() {
+            assert!(std::time::Instant::now() < deadline, "frontend was not unregistered");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collected_updates_wait_for_readers_instead_of_dropping() {
+        let state = Arc::new(RwLock::new(VacuumState::default()));
+        let (state_events, mut events_rx) = broadcast::channel(16);
+        let (state_updates, updates_rx) = mpsc::channel(STATE_UPDATE_BUFFER);
+        spawn_state_owner(state.clone(), state_events, updates_rx);
+
+        // A long GetState-style read used to make the whole tick get skipped
+        let reader = state.read().await;
+        let update = StateUpdate::VolumeState(VolumeState { level_percent: 77, muted: true });
+        send_updates(&state_updates, vec![Collected::ok(update.clone(), "pactl")]).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(events_rx.try_recv().is_err());
+        drop(reader);
+
+        assert_eq!(events_rx.recv().await.unwrap(), update);
+        assert_eq!(state.read().await.volume_state.level_percent, 77);
+
+        // A failed refresh keeps the last good value and explains itself
+        let failure = Collected::new(StateSection::VolumeState, "pactl", Err(anyhow::anyhow!("pactl not found")));
+        send_updates(&state_updates, vec![failure]).await;
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let state = state.read().await;
+            let meta = &state.sections[&StateSection::VolumeState];
+            if let Some(error) = &meta.last_error {
+                assert_eq!(error, "pactl not found");
+                assert!(meta.last_updated_ms.is_some());
+                assert_eq!(state.volume_state.level_percent, 77);
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "failure was not recorded");
+            drop(state);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collector_panic_marks_sections_failed() {
+        let sections = [StateSection::AudioStatus, StateSection::VolumeState];
+        let (_, updates) = collect_blocking(SystemCollector::new(), &sections, |_| panic!("pactl exploded")).await;
+
+        assert_eq!(updates.len(), 2);
+        for (collected, section) in updates.iter().zip(sections) {
+            assert_eq!(collected.section, section);
+            let error = collected.result.as_ref().unwrap_err();
+            assert!(error.starts_with("Collector panicked"), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_power_actions_wait_for_confirmation() {
+        // Only ever cancel here; confirming would really reboot the machine
+        let client = IpcClient::from_stream(spawn_test_connection(), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        let token = match client.request(IpcCommand::Reboot).await.unwrap() {
+            IpcResponse::ConfirmationRequired { token, command, expires_in_ms } => {
+                assert_eq!(command, "Reboot");
+                assert_eq!(expires_in_ms, 30_000);
+                token
+            }
+            other => panic!("expected a confirmation request, got {:?}", other),
+        };
+
+        assert!(matches!(client.request(IpcCommand::CancelAction(token.clone())).await.unwrap(), IpcResponse::Success));
+        assert!(matches!(client.request(IpcCommand::ConfirmAction(token)).await.unwrap(), IpcResponse::Error(_)));
+        assert!(matches!(client.request(IpcCommand::ConfirmAction("guess".to_string())).await.unwrap(), IpcResponse::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_and_cancel_power_action() {
+        let context = test_context();
+        let client = IpcClient::from_stream(spawn_test_connection_with(context.clone()), ipc::DEFAULT_MAX_MESSAGE_BYTES);
+
+        let response = client.request(IpcCommand::ScheduleReboot(ScheduleTime::AfterSecs(3600))).await.unwrap();
+        assert!(matches!(response, IpcResponse::Success));
+        let scheduled = context.schedule.current().unwrap();
+        assert_eq!(scheduled.action, PowerAction::Reboot);
+        assert!((3599..=3600).contains(&scheduled.remaining_secs));
+
+        assert!(matches!(client.request(IpcCommand::CancelScheduled).await.unwrap(), IpcResponse::Success));
+        assert!(context.schedule.current().is_none());
+        assert!(matches!(client.request(IpcCommand::CancelScheduled).await.unwrap(), IpcResponse::Error(_)));
+    }
+}
@@ -0,0 +1,407 @@
+use crate::audit::Caller;
+use crate::daemon::{IpcCommand, IpcResponse};
+use crate::schedule::ScheduleTime;
+use crate::state::{StateSection, StateUpdate, VacuumState};
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{RwLock, broadcast};
+use zbus::message::Header;
+use zbus::names::InterfaceName;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::Value;
+use zbus::{Connection, fdo};
+
+pub const BUS_NAME: &str = "dev.onyxdigital.Vacuum1";
+pub const OBJECT_PATH: &str = "/dev/onyxdigital/Vacuum1";
+pub const INTERFACE_NAME: &str = "dev.onyxdigital.Vacuum1";
+
+/// `BUS_NAME`, or `BUS_NAME.<instance>` for a named instance so it can run
+/// next to the default one.
+pub fn bus_name() -> String {
+    match &crate::instance::current().name {
+        Some(name) => format!("{}.{}", BUS_NAME, name),
+        None => BUS_NAME.to_string(),
+    }
+}
+
+/// Runs a command for a D-Bus caller the way an IPC connection would.
+pub type Dispatch = Arc<dyn Fn(IpcCommand, Caller) -> Pin<Box<dyn Future<Output = IpcResponse> + Send>> + Send + Sync>;
+
+#[derive(Debug, zbus::DBusError)]
+#[zbus(prefix = "dev.onyxdigital.Vacuum1.Error")]
+pub enum VacuumError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    /// The daemon answered `IpcResponse::Error`
+    Failed(String),
+    /// The action outlived its `[actions]` timeout
+    TimedOut(String),
+    PermissionDenied(String),
+}
+
+/// The daemon's commands as methods and its state sections as properties.
+/// Sections are `a{sv}` dictionaries (`StorageInfo` a list of them) with
+/// missing values left out; structured replies are JSON strings.
+struct VacuumInterface {
+    state: Arc<RwLock<VacuumState>>,
+    dispatch: Dispatch,
+}
+
+impl VacuumInterface {
+    async fn call(&self, connection: &Connection, header: &Header<'_>, command: IpcCommand) -> Result<IpcResponse, VacuumError> {
+        let caller = owner(connection, header, command.name()).await?;
+        match (self.dispatch)(command, caller).await {
+            IpcResponse::Error(e) => Err(VacuumError::Failed(e)),
+            IpcResponse::ActionTimedOut { command, timeout_ms } => {
+                Err(VacuumError::TimedOut(format!("{} timed out after {} ms", command, timeout_ms)))
+            }
+            response => Ok(response),
+        }
+    }
+
+    /// A section for a property read, which is as private as `GetState`.
+    async fn section(&self, connection: &Connection, header: Option<Header<'_>>, section: StateSection) -> fdo::Result<serde_json::Value> {
+        let header = header.ok_or_else(|| fdo::Error::AccessDenied("Property read without a sender".to_string()))?;
+        owner(connection, &header, property_name(section)).await.map_err(|e| match e {
+            VacuumError::PermissionDenied(message) => fdo::Error::AccessDenied(message),
+            VacuumError::ZBus(e) => e.into(),
+            other => fdo::Error::Failed(other.to_string()),
+        })?;
+        Ok(section_json(&self.state.read().await.section(section)))
+    }
+}
+
+#[zbus::interface(name = "dev.onyxdigital.Vacuum1")]
+impl VacuumInterface {
+    /// Daemon version, protocol and capabilities as JSON.
+    async fn hello(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+        protocol_version: u32,
+        client_name: String,
+    ) -> Result<String, VacuumError> {
+        let command = IpcCommand::Hello { protocol_version, client_name };
+        json(self.call(connection, &header, command).await?)
+    }
+
+    async fn toggle_overlay(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<bool, VacuumError> {
+        toggled(self.call(connection, &header, IpcCommand::ToggleOverlay).await?)
+    }
+
+    async fn show_overlay(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<bool, VacuumError> {
+        toggled(self.call(connection, &header, IpcCommand::ShowOverlay).await?)
+    }
+
+    async fn hide_overlay(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<bool, VacuumError> {
+        toggled(self.call(connection, &header, IpcCommand::HideOverlay).await?)
+    }
+
+    async fn get_state(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        json(self.call(connection, &header, IpcCommand::GetState).await?)
+    }
+
+    async fn get_audio_visualizer(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        json(self.call(connection, &header, IpcCommand::GetAudioVisualizer).await?)
+    }
+
+    async fn set_volume(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, level: u8) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::SetVolume(level)).await.map(drop)
+    }
+
+    async fn toggle_mute(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<bool, VacuumError> {
+        toggled(self.call(connection, &header, IpcCommand::ToggleMute).await?)
+    }
+
+    async fn toggle_wifi(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<bool, VacuumError> {
+        toggled(self.call(connection, &header, IpcCommand::ToggleWifi).await?)
+    }
+
+    async fn toggle_bluetooth(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<bool, VacuumError> {
+        toggled(self.call(connection, &header, IpcCommand::ToggleBluetooth).await?)
+    }
+
+    async fn toggle_vpn(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<bool, VacuumError> {
+        toggled(self.call(connection, &header, IpcCommand::ToggleVpn).await?)
+    }
+
+    /// Returns the confirmation token, or an empty string if the action ran.
+    async fn logout(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        Ok(token(self.call(connection, &header, IpcCommand::Logout).await?))
+    }
+
+    /// Returns the confirmation token, or an empty string if the action ran.
+    async fn reboot(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        Ok(token(self.call(connection, &header, IpcCommand::Reboot).await?))
+    }
+
+    /// Returns the confirmation token, or an empty string if the action ran.
+    async fn shutdown(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        Ok(token(self.call(connection, &header, IpcCommand::Shutdown).await?))
+    }
+
+    async fn confirm_action(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, token: String) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::ConfirmAction(token)).await.map(drop)
+    }
+
+    async fn cancel_action(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, token: String) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::CancelAction(token)).await.map(drop)
+    }
+
+    /// Shuts down at `unix_secs`.
+    async fn schedule_shutdown_at(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, unix_secs: u64) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::ScheduleShutdown(ScheduleTime::At(unix_secs))).await.map(drop)
+    }
+
+    async fn schedule_shutdown_in(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, secs: u64) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::ScheduleShutdown(ScheduleTime::AfterSecs(secs))).await.map(drop)
+    }
+
+    /// Reboots at `unix_secs`.
+    async fn schedule_reboot_at(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, unix_secs: u64) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::ScheduleReboot(ScheduleTime::At(unix_secs))).await.map(drop)
+    }
+
+    async fn schedule_reboot_in(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, secs: u64) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::ScheduleReboot(ScheduleTime::AfterSecs(secs))).await.map(drop)
+    }
+
+    async fn cancel_scheduled(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::CancelScheduled).await.map(drop)
+    }
+
+    async fn launch_app(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::LaunchApp).await.map(drop)
+    }
+
+    async fn launch_url(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, url: String) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::LaunchUrl(url)).await.map(drop)
+    }
+
+    /// Current config as JSON, with secrets redacted.
+    async fn get_config(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        json(self.call(connection, &header, IpcCommand::GetConfig).await?)
+    }
+
+    /// Applies a JSON merge patch and returns the new config.
+    async fn patch_config(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>, patch: String) -> Result<String, VacuumError> {
+        let patch = serde_json::from_str(&patch)
+            .map_err(|e| VacuumError::Failed(format!("Config patch is not valid JSON: {}", e)))?;
+        json(self.call(connection, &header, IpcCommand::PatchConfig(patch)).await?)
+    }
+
+    async fn reload_config(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::ReloadConfig).await.map(drop)
+    }
+
+    async fn get_health(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        json(self.call(connection, &header, IpcCommand::GetHealth).await?)
+    }
+
+    async fn status(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<String, VacuumError> {
+        json(self.call(connection, &header, IpcCommand::Status).await?)
+    }
+
+    async fn stop_daemon(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Header<'_>) -> Result<(), VacuumError> {
+        self.call(connection, &header, IpcCommand::StopDaemon).await.map(drop)
+    }
+
+    #[zbus(property)]
+    async fn user_info(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::UserInfo).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn system_info(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::SystemInfo).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn storage_info(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<Vec<HashMap<String, Value<'static>>>> {
+        self.section(connection, header, StateSection::StorageInfo).await.map(|section| list(&section))
+    }
+
+    #[zbus(property)]
+    async fn network_status(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::NetworkStatus).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn network_traffic(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::NetworkTraffic).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn audio_status(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::AudioStatus).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn volume_state(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::VolumeState).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn weather_info(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::WeatherInfo).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn launcher_shortcuts(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::LauncherShortcuts).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn toggles(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::Toggles).await.map(|section| dict(&section))
+    }
+
+    #[zbus(property)]
+    async fn overlay(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::Overlay).await.map(|section| dict(&section))
+    }
+
+    /// Empty when nothing is scheduled.
+    #[zbus(property)]
+    async fn scheduled_action(&self, #[zbus(connection)] connection: &Connection, #[zbus(header)] header: Option<Header<'_>>) -> fdo::Result<HashMap<String, Value<'static>>> {
+        self.section(connection, header, StateSection::ScheduledAction).await.map(|section| dict(&section))
+    }
+}
+
+/// Looks up who sent a method call and checks it is the daemon's own user.
+/// The session bus is per user, but it can be shared on purpose.
+async fn owner(connection: &Connection, header: &Header<'_>, what: &str) -> Result<Caller, VacuumError> {
+    let caller = caller(connection, header).await?;
+    if caller.uid != nix::unistd::getuid().as_raw() {
+        return Err(VacuumError::PermissionDenied(format!("{} is restricted to the daemon owner", what)));
+    }
+    Ok(caller)
+}
+
+/// Looks up who sent a method call, for policy checks and the audit log.
+async fn caller(connection: &Connection, header: &Header<'_>) -> Result<Caller, VacuumError> {
+    let sender = header.sender()
+        .ok_or_else(|| VacuumError::PermissionDenied("Method call without a sender".to_string()))?;
+    let credentials = fdo::DBusProxy::new(connection).await?
+        .get_connection_credentials(sender.clone().into())
+        .await
+        .map_err(zbus::Error::from)?;
+    let uid = credentials.unix_user_id()
+        .ok_or_else(|| VacuumError::PermissionDenied("The bus did not report the caller's uid".to_string()))?;
+    Ok(Caller { uid, pid: credentials.process_id().map(|pid| pid as i32) })
+}
+
+fn toggled(response: IpcResponse) -> Result<bool, VacuumError> {
+    match response {
+        IpcResponse::ToggleResult(on) => Ok(on),
+        other => Err(VacuumError::Failed(format!("Unexpected response: {:?}", other))),
+    }
+}
+
+fn token(response: IpcResponse) -> String {
+    match response {
+        IpcResponse::ConfirmationRequired { token, .. } => token,
+        _ => String::new(),
+    }
+}
+
+/// The payload of a response as JSON, without the variant wrapper.
+fn json(response: IpcResponse) -> Result<String, VacuumError> {
+    let value = match serde_json::to_value(&response).map_err(|e| VacuumError::Failed(e.to_string()))? {
+        serde_json::Value::Object(fields) if fields.len() == 1 => fields.into_iter().next().map(|(_, value)| value).unwrap_or_default(),
+        other => other,
+    };
+    Ok(value.to_string())
+}
+
+fn section_json(update: &StateUpdate) -> serde_json::Value {
+    match serde_json::to_value(update) {
+        Ok(serde_json::Value::Object(fields)) => fields.into_iter().next().map(|(_, value)| value).unwrap_or_default(),
+        _ => serde_json::Value::Null,
+    }
+}
+
+/// An object as `a{sv}`; anything else, like an unset section, is empty.
+fn dict(value: &serde_json::Value) -> HashMap<String, Value<'static>> {
+    match value {
+        serde_json::Value::Object(fields) => fields.iter()
+            .filter_map(|(key, value)| Some((key.clone(), variant(value)?)))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+fn list(value: &serde_json::Value) -> Vec<HashMap<String, Value<'static>>> {
+    value.as_array().map(|items| items.iter().map(dict).collect()).unwrap_or_default()
+}
+
+/// JSON as a variant. D-Bus has no null, so nulls are left out; integers are
+/// always `x` so a field keeps its type whatever its sign.
+fn variant(value: &serde_json::Value) -> Option<Value<'static>> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(flag) => Some(Value::from(*flag)),
+        serde_json::Value::Number(number) => number.as_i64().map(Value::from)
+            .or_else(|| number.as_u64().map(Value::from))
+            .or_else(|| number.as_f64().map(Value::from)),
+        serde_json::Value::String(text) => Some(Value::from(text.clone())),
+        serde_json::Value::Array(items) => Some(Value::from(items.iter().filter_map(variant).collect::<Vec<_>>())),
+        serde_json::Value::Object(_) => Some(Value::from(dict(value))),
+    }
+}
+
+/// Claims `bus_name()` on the session bus and serves the interface for as long
+/// as the returned connection is kept.
+pub async fn serve(state: Arc<RwLock<VacuumState>>, dispatch: Dispatch) -> Result<Connection> {
+    zbus::connection::Builder::session()?
+        .name(bus_name())?
+        .serve_at(OBJECT_PATH, VacuumInterface { state, dispatch })?
+        .build()
+        .await
+        .context("Failed to register on the session bus")
+}
+
+/// Emits `PropertiesChanged` for every section update until the daemon stops.
+pub async fn forward_changes(connection: &Connection, mut events: broadcast::Receiver<StateUpdate>) -> Result<()> {
+    let emitter = SignalEmitter::new(connection, OBJECT_PATH)?;
+    let interface = InterfaceName::from_static_str(INTERFACE_NAME)?;
+    loop {
+        let (changed, invalidated) = match events.recv().await {
+            Ok(update) => {
+                let value = match &update {
+                    StateUpdate::StorageInfo(_) => Value::from(list(&section_json(&update))),
+                    _ => Value::from(dict(&section_json(&update))),
+                };
+                (HashMap::from([(property_name(update.section()), value)]), Vec::new())
+            }
+            // Whatever was missed can't be replayed, so let clients refetch everything
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                (HashMap::new(), StateSection::ALL.iter().map(|section| property_name(*section)).collect())
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        fdo::Properties::properties_changed(&emitter, interface.clone(), changed, Cow::Owned(invalidated)).await?;
+    }
+}
+
+/// The property of a section, named like its `StateSection` variant.
+fn property_name(section: StateSection) -> &'static str {
+    match section {
+        StateSection::UserInfo => "UserInfo",
+        StateSection::SystemInfo => "SystemInfo",
+        StateSection::StorageInfo => "StorageInfo",
+        StateSection::NetworkStatus => "NetworkStatus",
+        StateSection::NetworkTraffic => "NetworkTraffic",
+        StateSection::AudioStatus => "AudioStatus",
+        StateSection::VolumeState => "VolumeState",
+        StateSection::WeatherInfo => "WeatherInfo",
+        StateSection::LauncherShortcuts => "LauncherShortcuts",
+        StateSection::Toggles => "Toggles",
+        StateSection::Overlay => "Overlay",
+        StateSection::ScheduledAction => "ScheduledAction",
+    }
+}
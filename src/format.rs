@@ -0,0 +1,250 @@
+use crate::state::StateSection;
+use serde_json::Value;
+use std::fmt::Write as _;
+
+/// How `state`/`--get-state` prints the state or a selection of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Toml,
+    /// `VACUUM_SYSTEM_INFO_CPU_LOAD_PERCENT=12.5` lines for `eval`
+    Env,
+    /// Aligned key/value rows with sizes and rates in human units
+    Table,
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            "env" => Ok(Self::Env),
+            "table" => Ok(Self::Table),
+            other => Err(format!("Unknown format '{}', use json, toml, env or table", other)),
+        }
+    }
+}
+
+/// Picks `paths` such as `system_info.cpu_load_percent` or `storage_info.0`
+/// out of the serialized state. No paths selects everything, one path its
+/// value, several an object keyed by path.
+pub fn select(state: &Value, paths: &[String]) -> Result<Value, String> {
+    match paths {
+        [] => Ok(state.clone()),
+        [path] => lookup(state, path),
+        paths => paths.iter()
+            .map(|path| Ok((path.clone(), lookup(state, path)?)))
+            .collect::<Result<serde_json::Map<_, _>, String>>()
+            .map(Value::Object),
+    }
+}
+
+fn lookup(state: &Value, path: &str) -> Result<Value, String> {
+    let mut keys = path.split('.');
+    let section = keys.next().unwrap_or_default();
+    // Sections may also be named like their `StateSection` variant
+    let section = match state.get(section) {
+        Some(_) => section,
+        None => StateSection::from_name(section).map(|section| section.name()).unwrap_or(section),
+    };
+
+    let mut value = state.get(section).ok_or_else(|| format!("No state field '{}'", section))?;
+    for key in keys {
+        value = match value {
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|index| items.get(index)),
+            _ => value.get(key),
+        }
+        .ok_or_else(|| format!("No state field '{}'", path))?;
+    }
+    Ok(value.clone())
+}
+
+/// Renders a selection; `root` is the single selected path, if any, and
+/// names scalars and prefixes flattened keys.
+pub fn render(value: &Value, root: Option<&str>, format: OutputFormat) -> Result<String, String> {
+    match format {
+        OutputFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        OutputFormat::Toml => {
+            let mut value = without_nulls(value);
+            if !value.is_object() {
+                // A TOML document has to be a table
+                let key = root.and_then(|root| root.rsplit('.').next()).unwrap_or("value");
+                value = Value::Object(serde_json::Map::from_iter([(key.to_string(), value)]));
+            }
+            toml::to_string_pretty(&value).map_err(|e| e.to_string())
+        }
+        OutputFormat::Env => {
+            let mut out = String::new();
+            for (key, value) in flatten(value, root.unwrap_or_default()) {
+                let name: String = key.chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+                    .collect();
+                let _ = writeln!(out, "VACUUM_{}={}", name.trim_matches('_'), shell_quote(&plain(value)));
+            }
+            Ok(out)
+        }
+        OutputFormat::Table => {
+            let rows: Vec<(String, String)> = flatten(value, root.unwrap_or_default())
+                .into_iter()
+                .map(|(key, value)| {
+                    let human = human(&key, value);
+                    (key, human)
+                })
+                .collect();
+            let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+            let mut out = String::new();
+            for (key, value) in rows {
+                let _ = writeln!(out, "{:width$}  {}", key, value, width = width);
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// Dotted keys of every leaf. Arrays of plain values stay one leaf.
+fn flatten<'a>(value: &'a Value, prefix: &str) -> Vec<(String, &'a Value)> {
+    let join = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+    match value {
+        Value::Object(fields) => fields.iter().flat_map(|(key, value)| flatten(value, &join(key))).collect(),
+        Value::Array(items) if items.iter().any(|item| item.is_object() || item.is_array()) => items.iter()
+            .enumerate()
+            .flat_map(|(index, item)| flatten(item, &join(&index.to_string())))
+            .collect(),
+        _ => vec![(prefix.to_string(), value)],
+    }
+}
+
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields.iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().filter(|item| !item.is_null()).map(without_nulls).collect()),
+        other => other.clone(),
+    }
+}
+
+/// A leaf as text, without JSON quoting.
+fn plain(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(plain).collect::<Vec<_>>().join(" "),
+        other => other.to_string(),
+    }
+}
+
+fn shell_quote(text: &str) -> String {
+    if !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || "._-+:/@".contains(c)) {
+        return text.to_string();
+    }
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// A leaf in human units, chosen by the field's suffix.
+fn human(key: &str, value: &Value) -> String {
+    let field = key.rsplit('.').find(|part| part.parse::<usize>().is_err()).unwrap_or(key);
+    let Some(number) = value.as_f64() else {
+        return match value {
+            Value::Null => "-".to_string(),
+            other => plain(other),
+        };
+    };
+
+    if field.ends_with("_bytes") {
+        format_bytes(number)
+    } else if field.ends_with("_kbps") {
+        format_rate(number)
+    } else if field.ends_with("_percent") {
+        format!("{:.1}%", number)
+    } else if field.ends_with("_ghz") {
+        format!("{:.2} GHz", number)
+    } else if field.ends_with("_secs") {
+        format_duration(number as u64)
+    } else if field.ends_with("_ms") && number > 0.0 {
+        // Every `_ms` field in the state is a Unix timestamp
+        crate::audit::format_time(number as u64)
+    } else {
+        plain(value)
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// A `NetworkTraffic` rate, collected as KiB transferred per second.
+pub fn format_rate(kbps: f64) -> String {
+    format_bytes(kbps * 1024.0) + "/s"
+}
+
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{DiskInfo, VacuumState};
+
+    fn sample_state() -> Value {
+        let mut state = VacuumState::default();
+        state.system_info.ram_used_bytes = 3 * 1024 * 1024 * 1024;
+        state.network_traffic.rx_kbps = 2048.0;
+        state.storage_info.push(DiskInfo {
+            device: "/dev/sda1".to_string(),
+            mountpoint: "/home/o'brien".to_string(),
+            fs_type: "ext4".to_string(),
+            used_bytes: 512,
+            total_bytes: 1024,
+        });
+        serde_json::to_value(state).unwrap()
+    }
+
+    #[test]
+    fn test_select_paths() {
+        let state = sample_state();
+        assert_eq!(select(&state, &["system_info.ram_used_bytes".to_string()]).unwrap(), 3u64 * 1024 * 1024 * 1024);
+        assert_eq!(select(&state, &["StorageInfo.0.device".to_string()]).unwrap(), "/dev/sda1");
+        assert!(select(&state, &["system_info.nope".to_string()]).is_err());
+
+        let both = select(&state, &["toggles".to_string(), "volume_state.muted".to_string()]).unwrap();
+        assert_eq!(both["volume_state.muted"], false);
+    }
+
+    #[test]
+    fn test_formats() {
+        let state = sample_state();
+
+        let table = render(&state["network_traffic"], Some("network_traffic"), OutputFormat::Table).unwrap();
+        assert!(table.lines().any(|line| line.starts_with("network_traffic.rx_kbps ") && line.ends_with("  2.0 MiB/s")));
+        let table = render(&state["system_info"], Some("system_info"), OutputFormat::Table).unwrap();
+        assert!(table.contains("3.0 GiB"));
+
+        let env = render(&state["storage_info"], Some("storage_info"), OutputFormat::Env).unwrap();
+        assert!(env.contains("VACUUM_STORAGE_INFO_0_MOUNTPOINT='/home/o'\\''brien'\n"));
+        assert!(env.contains("VACUUM_STORAGE_INFO_0_TOTAL_BYTES=1024\n"));
+
+        let toml = render(&state["system_info"]["cpu_cores"], Some("system_info.cpu_cores"), OutputFormat::Toml).unwrap();
+        assert!(toml.starts_with("cpu_cores = "));
+        assert!(render(&state, None, OutputFormat::Toml).is_ok());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::state::{StateSection, VacuumState, unix_time_ms};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+pub const SYSTEM_LOOP: &str = "system";
+pub const NETWORK_LOOP: &str = "network";
+pub const AUDIO_LOOP: &str = "audio";
+pub const WEATHER_LOOP: &str = "weather";
+
+/// A loop is considered stuck once it misses this many of its own ticks,
+/// and its sections stale once they are that many intervals old.
+const MISSED_TICKS_BEFORE_STALL: u32 = 3;
+
+/// Records when each update loop last finished a tick. The daemon reports
+/// readiness once every loop that gates it has ticked, and only pings the
+/// systemd watchdog while no loop has fallen far behind its interval.
+#[derive(Clone)]
+pub struct LoopHeartbeats {
+    loops: Arc<Mutex<HashMap<&'static str, LoopHeartbeat>>>,
+    ready: watch::Sender<bool>,
+}
+
+struct LoopHeartbeat {
+    interval: Duration,
+    last_tick: Option<Instant>,
+    last_duration: Option<Duration>,
+    gates_ready: bool,
+}
+
+impl Default for LoopHeartbeats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoopHeartbeats {
+    pub fn new() -> Self {
+        Self {
+            loops: Arc::new(Mutex::new(HashMap::new())),
+            ready: watch::channel(false).0,
+        }
+    }
+
+    pub fn register(&self, name: &'static str, interval: Duration, gates_ready: bool) {
+        let mut loops = self.loops.lock().unwrap();
+        let (last_tick, last_duration) = loops.get(name)
+            .map(|heartbeat| (heartbeat.last_tick, heartbeat.last_duration))
+            .unwrap_or_default();
+        loops.insert(name, LoopHeartbeat { interval, last_tick, last_duration, gates_ready });
+    }
+
+    /// Records a finished tick that took `duration`.
+    pub fn beat(&self, name: &'static str, duration: Duration) {
+        let mut loops = self.loops.lock().unwrap();
+        if let Some(heartbeat) = loops.get_mut(name) {
+            heartbeat.last_tick = Some(Instant::now());
+            heartbeat.last_duration = Some(duration);
+        }
+        let all_ticked = loops.values()
+            .filter(|heartbeat| heartbeat.gates_ready)
+            .all(|heartbeat| heartbeat.last_tick.is_some());
+        self.ready.send_if_modified(|ready| {
+            let changed = all_ticked && !*ready;
+            *ready |= all_ticked;
+            changed
+        });
+    }
+
+    /// Becomes `true` once every loop that gates readiness has ticked.
+    pub fn ready(&self) -> watch::Receiver<bool> {
+        self.ready.subscribe()
+    }
+
+    /// Names of loops that have not ticked within their allowance.
+    pub fn stalled(&self) -> Vec<String> {
+        self.loops()
+            .into_iter()
+            .filter(|health| health.stalled)
+            .map(|health| health.name)
+            .collect()
+    }
+
+    fn interval(&self, name: &str) -> Option<Duration> {
+        self.loops.lock().unwrap().get(name).map(|heartbeat| heartbeat.interval)
+    }
+
+    /// Every registered loop, sorted by name.
+    pub fn loops(&self) -> Vec<LoopHealth> {
+        let loops = self.loops.lock().unwrap();
+        let mut health: Vec<_> = loops.iter()
+            .map(|(name, heartbeat)| {
+                let since_tick = heartbeat.last_tick.map(|last_tick| last_tick.elapsed());
+                LoopHealth {
+                    name: name.to_string(),
+                    interval_secs: heartbeat.interval.as_secs(),
+                    last_tick_ms_ago: since_tick.map(|elapsed| elapsed.as_millis() as u64),
+                    last_duration_ms: heartbeat.last_duration.map(|duration| duration.as_millis() as u64),
+                    stalled: since_tick.is_some_and(|elapsed| elapsed > heartbeat.interval * MISSED_TICKS_BEFORE_STALL),
+                }
+            })
+            .collect();
+        health.sort_by(|a, b| a.name.cmp(&b.name));
+        health
+    }
+}
+
+/// Answer to `GetHealth`: how every state section and update loop is doing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// False if any section is failing or stale, or any loop has stalled
+    pub healthy: bool,
+    pub sections: Vec<SectionHealth>,
+    pub loops: Vec<LoopHealth>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HealthStatus {
+    /// Refreshed recently without errors
+    Ok,
+    /// Not collected yet
+    Pending,
+    /// The latest refresh failed; the section still holds older data
+    Failing,
+    /// No successful refresh for several collection intervals
+    Stale,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionHealth {
+    pub section: StateSection,
+    pub status: HealthStatus,
+    pub source: Option<String>,
+    pub last_updated_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopHealth {
+    pub name: String,
+    pub interval_secs: u64,
+    pub last_tick_ms_ago: Option<u64>,
+    /// How long the last tick took to collect
+    #[serde(default)]
+    pub last_duration_ms: Option<u64>,
+    pub stalled: bool,
+}
+
+/// The loop that refreshes a section, or `None` for sections that only
+/// change through commands or config.
+fn collecting_loop(section: StateSection) -> Option<&'static str> {
+    match section {
+        StateSection::UserInfo
+        | StateSection::SystemInfo
+        | StateSection::StorageInfo
+        | StateSection::Toggles => Some(SYSTEM_LOOP),
+        StateSection::NetworkStatus | StateSection::NetworkTraffic => Some(NETWORK_LOOP),
+        StateSection::AudioStatus | StateSection::VolumeState => Some(AUDIO_LOOP),
+        StateSection::WeatherInfo => Some(WEATHER_LOOP),
+        StateSection::LauncherShortcuts
+        | StateSection::Overlay
+        | StateSection::ScheduledAction => None,
+    }
+}
+
+pub fn health_report(state: &VacuumState, heartbeats: &LoopHeartbeats) -> HealthReport {
+    let now_ms = unix_time_ms();
+    let sections: Vec<_> = StateSection::ALL.iter()
+        .map(|&section| {
+            let meta = state.sections.get(&section).cloned().unwrap_or_default();
+            let interval = collecting_loop(section).and_then(|name| heartbeats.interval(name));
+            let status = match (&meta.last_error, meta.last_updated_ms, interval) {
+                (Some(_), _, _) => HealthStatus::Failing,
+                (None, None, Some(_)) => HealthStatus::Pending,
+                (None, Some(updated_ms), Some(interval))
+                    if now_ms.saturating_sub(updated_ms) > (interval * MISSED_TICKS_BEFORE_STALL).as_millis() as u64 =>
+                {
+                    HealthStatus::Stale
+                }
+                _ => HealthStatus::Ok,
+            };
+
+            SectionHealth {
+                section,
+                status,
+                source: meta.source,
+                last_updated_ms: meta.last_updated_ms,
+                last_error: meta.last_error,
+            }
+        })
+        .collect();
+    let loops = heartbeats.loops();
+
+    let healthy = sections.iter()
+        .all(|section| matches!(section.status, HealthStatus::Ok | HealthStatus::Pending))
+        && loops.iter().all(|health| !health.stalled);
+
+    HealthReport { healthy, sections, loops }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_of(report: &HealthReport, section: StateSection) -> HealthStatus {
+        report.sections.iter().find(|health| health.section == section).unwrap().status.clone()
+    }
+
+    #[test]
+    fn test_section_status_follows_metadata() {
+        let heartbeats = LoopHeartbeats::new();
+        heartbeats.register(SYSTEM_LOOP, Duration::from_secs(10), true);
+        heartbeats.register(AUDIO_LOOP, Duration::from_secs(3), true);
+
+        let mut state = VacuumState::default();
+        state.mark_updated(StateSection::SystemInfo, "sysinfo");
+        state.mark_failed(StateSection::Toggles, "bluetoothctl", "Failed to check Bluetooth status".to_string());
+        state.mark_updated(StateSection::VolumeState, "pactl");
+        state.sections.get_mut(&StateSection::VolumeState).unwrap().last_updated_ms = Some(unix_time_ms() - 60_000);
+
+        let report = health_report(&state, &heartbeats);
+        assert_eq!(status_of(&report, StateSection::SystemInfo), HealthStatus::Ok);
+        assert_eq!(status_of(&report, StateSection::Toggles), HealthStatus::Failing);
+        assert_eq!(status_of(&report, StateSection::VolumeState), HealthStatus::Stale);
+        assert_eq!(status_of(&report, StateSection::StorageInfo), HealthStatus::Pending);
+        assert_eq!(status_of(&report, StateSection::Overlay), HealthStatus::Ok);
+        assert!(!report.healthy);
+    }
+}
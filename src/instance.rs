@@ -0,0 +1,257 @@
+use crate::client::{ConnectOptions, IpcClient};
+use crate::daemon::{IpcCommand, IpcResponse, get_socket_path};
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// How long `--replace` waits for the running daemon after each step
+/// (IPC request, SIGTERM, SIGKILL) before trying the next.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+static CURRENT: OnceLock<Instance> = OnceLock::new();
+
+/// Which daemon this process is, or talks to: the global `--instance`,
+/// `--socket` and `--config` options, with the paths falling back to
+/// `VACUUM_SOCKET` and `VACUUM_CONFIG`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Instance {
+    /// Keeps the runtime directory, config, audit log and bus name apart
+    pub name: Option<String>,
+    pub socket: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+}
+
+impl Instance {
+    /// Takes the global options out of `args`, wherever they appear.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut instance = Self::default();
+        let mut rest = Vec::with_capacity(args.len());
+        let mut iter = args.drain(..);
+        while let Some(arg) = iter.next() {
+            let slot = match arg.as_str() {
+                "--instance" => {
+                    let name = iter.next().ok_or("--instance needs a name")?;
+                    validate_name(&name)?;
+                    instance.name = Some(name);
+                    continue;
+                }
+                "--socket" => &mut instance.socket,
+                "--config" => &mut instance.config,
+                _ => {
+                    rest.push(arg);
+                    continue;
+                }
+            };
+            *slot = Some(PathBuf::from(iter.next().ok_or_else(|| format!("{} needs a path", arg))?));
+        }
+        drop(iter);
+        *args = rest;
+        Ok(instance.with_env())
+    }
+
+    fn with_env(mut self) -> Self {
+        let var = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        self.socket = self.socket.or_else(|| var("VACUUM_SOCKET"));
+        self.config = self.config.or_else(|| var("VACUUM_CONFIG"));
+        self
+    }
+
+    /// Makes this the instance for the rest of the process; call it before
+    /// any path is looked up.
+    pub fn install(self) {
+        if CURRENT.set(self).is_err() {
+            tracing::warn!("Instance options were already set");
+        }
+    }
+
+    /// The options that select this instance, for commands that run the
+    /// binary again.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(name) = &self.name {
+            args.extend(["--instance".to_string(), name.clone()]);
+        }
+        if let Some(socket) = &self.socket {
+            args.extend(["--socket".to_string(), socket.display().to_string()]);
+        }
+        if let Some(config) = &self.config {
+            args.extend(["--config".to_string(), config.display().to_string()]);
+        }
+        args
+    }
+}
+
+/// The installed instance, or one from the environment alone.
+pub fn current() -> &'static Instance {
+    CURRENT.get_or_init(|| Instance::default().with_env())
+}
+
+/// Names end up in paths and the D-Bus name, so they start with a letter and
+/// stick to letters, digits, `-` and `_`.
+fn validate_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid instance name '{}': use letters, digits, '-' and '_', starting with a letter", name))
+    }
+}
+
+/// Exclusive `flock` on the PID file, held for the daemon's lifetime. The
+/// kernel drops it when the process dies, so a crash never leaves it behind.
+pub struct InstanceLock {
+    file: Flock<File>,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Takes the lock without waiting; `None` if another daemon holds it.
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        loop {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(0o600)
+                .open(path)
+                .with_context(|| format!("Failed to open PID file {:?}", path))?;
+            let file = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+                Ok(file) => file,
+                Err((_, Errno::EWOULDBLOCK)) => return Ok(None),
+                Err((_, e)) => return Err(e).with_context(|| format!("Failed to lock PID file {:?}", path)),
+            };
+
+            // The previous holder removes the file on exit; if that happened
+            // between our open and lock, we locked an orphan and must retry
+            let locked = file.metadata()?;
+            match std::fs::metadata(path) {
+                Ok(current) if current.dev() == locked.dev() && current.ino() == locked.ino() => {
+                    return Ok(Some(Self { file, path: path.to_path_buf() }));
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Replaces the file's contents with our PID.
+    pub fn write_pid(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.write_all_at(std::process::id().to_string().as_bytes(), 0)
+            .context("Failed to write PID file")
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so nobody can lock the file we delete
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// PID of the daemon holding the lock, as it recorded it.
+pub fn read_pid(path: &Path) -> Option<i32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Stops the daemon holding the lock and takes the lock over: asks it to
+/// stop over IPC, then sends SIGTERM, and SIGKILL if it is wedged.
+pub async fn take_over(path: &Path) -> Result<InstanceLock> {
+    let pid = read_pid(path);
+    match request_stop().await {
+        Ok(()) => {
+            tracing::info!("Asked the running daemon to stop");
+            if let Some(lock) = wait_for_lock(path).await? {
+                return Ok(lock);
+            }
+        }
+        Err(e) => tracing::warn!("Could not ask the running daemon to stop: {:#}", e),
+    }
+
+    let pid = pid.ok_or_else(|| anyhow::anyhow!("The running daemon did not stop and its PID file is empty"))?;
+    for signal in [Signal::SIGTERM, Signal::SIGKILL] {
+        tracing::warn!("Sending {} to daemon {}", signal, pid);
+        match kill(Pid::from_raw(pid), signal) {
+            Ok(()) | Err(Errno::ESRCH) => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to signal daemon {}", pid)),
+        }
+        if let Some(lock) = wait_for_lock(path).await? {
+            return Ok(lock);
+        }
+    }
+    Err(anyhow::anyhow!("Daemon {} still holds {:?} after SIGKILL", pid, path))
+}
+
+async fn request_stop() -> Result<()> {
+    // Only the daemon that is already there, and no waiting for one to appear
+    let options = ConnectOptions { autostart: false, retry_for: Duration::ZERO, ..ConnectOptions::default() };
+    let client = IpcClient::connect_to(&get_socket_path(), options).await?;
+    let response = tokio::time::timeout(Duration::from_secs(2), client.request(IpcCommand::StopDaemon))
+        .await
+        .context("The daemon did not answer")??;
+    match response {
+        IpcResponse::Success => Ok(()),
+        IpcResponse::Error(e) => Err(anyhow::anyhow!(e)),
+        other => Err(anyhow::anyhow!("Unexpected response: {:?}", other)),
+    }
+}
+
+async fn wait_for_lock(path: &Path) -> Result<Option<InstanceLock>> {
+    let deadline = tokio::time::Instant::now() + STOP_GRACE;
+    loop {
+        if let Some(lock) = InstanceLock::try_acquire(path)? {
+            return Ok(Some(lock));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive_and_removes_file() {
+        let path = std::env::temp_dir().join(format!("vacuum-instance-test-{}.pid", std::process::id()));
+        let mut lock = InstanceLock::try_acquire(&path).unwrap().unwrap();
+        lock.write_pid().unwrap();
+        assert_eq!(read_pid(&path), Some(std::process::id() as i32));
+
+        // flock is per open file description, so a second open conflicts even in-process
+        assert!(InstanceLock::try_acquire(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(!path.exists());
+        drop(InstanceLock::try_acquire(&path).unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_global_options_are_taken_out() {
+        let mut args: Vec<String> = ["vacuum-launcher", "--instance", "test", "status", "--socket", "/tmp/v.sock", "--json"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let instance = Instance::from_args(&mut args).unwrap();
+        assert_eq!(args, ["vacuum-launcher", "status", "--json"]);
+        assert_eq!(instance.name.as_deref(), Some("test"));
+        assert_eq!(instance.socket, Some(PathBuf::from("/tmp/v.sock")));
+        assert_eq!(instance.args()[..4], ["--instance", "test", "--socket", "/tmp/v.sock"]);
+
+        let mut args = vec!["--instance".to_string(), "../etc".to_string()];
+        assert!(Instance::from_args(&mut args).is_err());
+        let mut args = vec!["--config".to_string()];
+        assert!(Instance::from_args(&mut args).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{Result, Context};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Bumped whenever `IpcCommand` or `IpcResponse` change incompatibly.
+/// Clients learn the daemon's version and command set from `Hello`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Upper bound on a single IPC frame unless overridden by `[ipc] max_message_bytes`.
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+const LENGTH_PREFIX_BYTES: usize = 4;
+
+// Every IPC message is a big-endian u32 byte length followed by that many
+// bytes of JSON. Reads and writes loop until the whole frame has moved, so
+// a message is never truncated by a short read on the socket.
+
+/// Reads one frame. Returns `Ok(None)` if the peer closed the stream cleanly
+/// before sending any part of a new frame.
+pub async fn read_frame<R>(reader: &mut R, max_len: usize) -> Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; LENGTH_PREFIX_BYTES];
+    let mut filled = 0;
+    while filled < LENGTH_PREFIX_BYTES {
+        let n = reader.read(&mut header[filled..]).await
+            .context("Failed to read IPC frame header")?;
+        if n == 0 {
+            if filled == 0 {
+                return Ok(None);
+            }
+            return Err(anyhow::anyhow!("Connection closed in the middle of a frame header"));
+        }
+        filled += n;
+    }
+
+    let len = u32::from_be_bytes(header) as usize;
+    if len > max_len {
+        return Err(anyhow::anyhow!(
+            "IPC frame of {} bytes exceeds the maximum of {} bytes", len, max_len
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await
+        .with_context(|| format!("Connection closed before the full {} byte frame arrived", len))?;
+
+    Ok(Some(payload))
+}
+
+pub async fn write_frame<W>(writer: &mut W, payload: &[u8], max_len: usize) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    if payload.len() > max_len || payload.len() > u32::MAX as usize {
+        return Err(anyhow::anyhow!(
+            "IPC frame of {} bytes exceeds the maximum of {} bytes", payload.len(), max_len
+        ));
+    }
+
+    writer.write_all(&(payload.len() as u32).to_be_bytes()).await
+        .context("Failed to write IPC frame header")?;
+    writer.write_all(payload).await
+        .context("Failed to write IPC frame payload")?;
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn read_message<R, T>(reader: &mut R, max_len: usize) -> Result<Option<T>>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    match read_frame(reader, max_len).await? {
+        Some(payload) => Ok(Some(serde_json::from_slice(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn write_message<W, T>(writer: &mut W, message: &T, max_len: usize) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(message)?;
+    write_frame(writer, &payload, max_len).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_round_trip_with_partial_io() {
+        // A 3 byte pipe forces every read and write to be split many times
+        let (mut client, mut server) = tokio::io::duplex(3);
+        let payload: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+
+        let expected = payload.clone();
+        let writer = tokio::spawn(async move {
+            write_frame(&mut client, &payload, DEFAULT_MAX_MESSAGE_BYTES).await.unwrap();
+            write_frame(&mut client, b"second", DEFAULT_MAX_MESSAGE_BYTES).await.unwrap();
+        });
+
+        let first = read_frame(&mut server, DEFAULT_MAX_MESSAGE_BYTES).await.unwrap();
+        assert_eq!(first, Some(expected));
+        let second = read_frame(&mut server, DEFAULT_MAX_MESSAGE_BYTES).await.unwrap();
+        assert_eq!(second, Some(b"second".to_vec()));
+
+        writer.await.unwrap();
+        assert_eq!(read_frame(&mut server, DEFAULT_MAX_MESSAGE_BYTES).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_rejected() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&1024u32.to_be_bytes()).await.unwrap();
+
+        assert!(read_frame(&mut server, 512).await.is_err());
+        assert!(write_frame(&mut client, &[0u8; 1024], 512).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_frame_is_an_error() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&10u32.to_be_bytes()).await.unwrap();
+        client.write_all(b"abc").await.unwrap();
+        drop(client);
+
+        assert!(read_frame(&mut server, DEFAULT_MAX_MESSAGE_BYTES).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_header_is_an_error() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[0u8, 0]).await.unwrap();
+        drop(client);
+
+        assert!(read_frame(&mut server, DEFAULT_MAX_MESSAGE_BYTES).await.is_err());
+    }
+}
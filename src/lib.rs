@@ -1,7 +1,21 @@
 pub mod daemon;
+pub mod ipc;
+pub mod client;
+pub mod auth;
 pub mod state;
 pub mod config;
 pub mod collectors;
 pub mod actions;
 pub mod weather;
-pub mod cava;
\ No newline at end of file
+pub mod cava;
+pub mod systemd;
+pub mod health;
+pub mod metrics;
+pub mod confirm;
+pub mod schedule;
+pub mod audit;
+pub mod cli;
+pub mod format;
+pub mod bar;
+pub mod dbus;
+pub mod instance;
\ No newline at end of file
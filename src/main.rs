@@ -1,55 +1,106 @@
 use anyhow::Result;
 use std::env;
-use vacuum_launcher::daemon::{VacuumDaemon, send_ipc_command, IpcCommand};
+use vacuum_launcher::audit::{self, AuditQuery};
+use vacuum_launcher::cli;
+use vacuum_launcher::config::{Config, get_config_path, load_config_from};
+use vacuum_launcher::daemon::{VacuumDaemon, send_ipc_command, IpcCommand, IpcResponse};
+use vacuum_launcher::instance::Instance;
+use vacuum_launcher::state::unix_time_ms;
+use vacuum_launcher::systemd;
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    // Before the runtime starts its worker threads
+    systemd::take_activation_env();
+    tokio::runtime::Runtime::new()?.block_on(run())
+}
+
+async fn run() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter("info")
         .try_init()
         .ok();
 
-    let args: Vec<String> = env::args().collect();
-    
+    let mut args: Vec<String> = env::args().collect();
+    match Instance::from_args(&mut args) {
+        Ok(instance) => instance.install(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(cli::EXIT_USAGE);
+        }
+    }
+
     match args.get(1).map(|s| s.as_str()) {
         Some("--daemon") => {
-            let mut daemon = VacuumDaemon::new()?;
+            let replace = args[2..].iter().any(|arg| arg == "--replace");
+            let mut daemon = VacuumDaemon::new()?.replacing(replace);
             daemon.run().await?;
         }
         Some("--toggle") => {
             match send_ipc_command(IpcCommand::ToggleOverlay).await {
+                Ok(IpcResponse::ToggleResult(visible)) => {
+                    println!("Overlay {}", if visible { "shown" } else { "hidden" });
+                }
+                Ok(IpcResponse::Error(e)) => {
+                    eprintln!("Failed to toggle overlay: {}", e);
+                    std::process::exit(1);
+                }
                 Ok(_) => {
                     println!("Toggle command sent successfully");
                 }
                 Err(e) => {
                     eprintln!("Failed to send toggle command: {}", e);
-                    std::process::exit(1);
+                    std::process::exit(cli::EXIT_UNREACHABLE);
                 }
             }
         }
         Some("--get-state") => {
-            match send_ipc_command(IpcCommand::GetState).await {
-                Ok(response) => {
-                    println!("{}", serde_json::to_string_pretty(&response)?);
-                }
-                Err(e) => {
-                    eprintln!("Failed to get state: {}", e);
-                    std::process::exit(1);
-                }
+            let mut state_args = vec!["state".to_string()];
+            state_args.extend_from_slice(&args[2..]);
+            std::process::exit(cli::run(&state_args).await);
+        }
+        Some("--audit-log") => {
+            if let Err(e) = show_audit_log(&args[2..]) {
+                eprintln!("Failed to read audit log: {:#}", e);
+                std::process::exit(1);
             }
         }
-        _ => {
-            println!("Vacuum Launcher");
-            println!();
-            println!("USAGE:");
-            println!("    vacuum-launcher --daemon    Start the background service");
-            println!("    vacuum-launcher --toggle    Toggle the overlay display");
-            println!("    vacuum-launcher --get-state Show current system state");
-            println!();
-            println!("The daemon must be running before using --toggle.");
-            println!("Configure hotkey (default Super+Shift+Space) to run --toggle.");
+        None | Some("--help") | Some("-h") => cli::print_usage(),
+        Some(_) => std::process::exit(cli::run(&args[1..]).await),
+    }
+
+    Ok(())
+}
+
+/// Prints audit records matching the `--audit-log` filters, oldest first.
+fn show_audit_log(args: &[String]) -> Result<()> {
+    let now_ms = unix_time_ms();
+    let mut query = AuditQuery::default();
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--since" => query.since_ms = Some(audit::parse_time(value()?, now_ms)?),
+            "--until" => query.until_ms = Some(audit::parse_time(value()?, now_ms)?),
+            "--command" => query.command = Some(value()?.clone()),
+            "--json" => json = true,
+            other => return Err(anyhow::anyhow!("Unknown option '{}'", other)),
         }
     }
 
+    // Reading the log shouldn't create a config file as a side effect
+    let config = match get_config_path() {
+        Ok(path) if path.exists() => load_config_from(&path)?,
+        _ => Config::default(),
+    };
+
+    for record in audit::query(&config.audit, &query)? {
+        if json {
+            println!("{}", serde_json::to_string(&record)?);
+        } else {
+            println!("{}", record);
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,302 @@
+use crate::state::VacuumState;
+use anyhow::{Result, Context};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::RwLock;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Requests larger than this are not scrapes and get dropped.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Accept failures (usually running out of file descriptors) back off up to this long.
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Where the exporter listens: `127.0.0.1:9477`-style loopback addresses,
+/// or `unix:/path/to/socket`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricsListen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl MetricsListen {
+    pub fn parse(listen: &str) -> Result<Self> {
+        if let Some(path) = listen.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(anyhow::anyhow!("Metrics socket path is empty"));
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+
+        let addr: SocketAddr = listen.parse()
+            .with_context(|| format!("Invalid metrics listen address '{}'", listen))?;
+        // Workstation metrics aren't meant for the network at large
+        if !addr.ip().is_loopback() {
+            return Err(anyhow::anyhow!("Metrics listen address {} is not a loopback address", addr));
+        }
+        Ok(Self::Tcp(addr))
+    }
+}
+
+/// Serves `/metrics` until the task is dropped. A Unix socket is created
+/// owner-only and left for the caller to remove.
+pub async fn serve(listen: &MetricsListen, state: Arc<RwLock<VacuumState>>) -> Result<()> {
+    match listen {
+        MetricsListen::Tcp(addr) => {
+            let listener = TcpListener::bind(addr).await
+                .with_context(|| format!("Failed to bind metrics exporter to {}", addr))?;
+            tracing::info!("Serving metrics on http://{}/metrics", addr);
+            let mut backoff = AcceptBackoff::default();
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        backoff.reset();
+                        tokio::spawn(handle_scrape(stream, state.clone()));
+                    }
+                    Err(e) => backoff.wait(e).await,
+                }
+            }
+        }
+        MetricsListen::Unix(path) => {
+            remove_stale_socket(path)?;
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("Failed to bind metrics exporter to {:?}", path))?;
+            crate::auth::restrict_socket(path, None)?;
+            tracing::info!("Serving metrics on unix:{}", path.display());
+            let mut backoff = AcceptBackoff::default();
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        backoff.reset();
+                        tokio::spawn(handle_scrape(stream, state.clone()));
+                    }
+                    Err(e) => backoff.wait(e).await,
+                }
+            }
+        }
+    }
+}
+
+/// Removes a socket left behind by an earlier run. Anything else at the
+/// path is someone's file, so refuse rather than delete it.
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to inspect metrics socket path {:?}", path)),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(anyhow::anyhow!("Metrics socket path {:?} exists and is not a socket", path));
+    }
+    std::fs::remove_file(path)
+        .with_context(|| format!("Failed to remove stale metrics socket {:?}", path))
+}
+
+/// Keeps the exporter alive through transient accept errors.
+#[derive(Default)]
+struct AcceptBackoff {
+    delay: Option<Duration>,
+}
+
+impl AcceptBackoff {
+    async fn wait(&mut self, error: std::io::Error) {
+        let delay = self.delay.map_or(Duration::from_millis(10), |delay| (delay * 2).min(MAX_ACCEPT_BACKOFF));
+        tracing::warn!("Failed to accept metrics connection, retrying in {:?}: {}", delay, error);
+        self.delay = Some(delay);
+        tokio::time::sleep(delay).await;
+    }
+
+    fn reset(&mut self) {
+        self.delay = None;
+    }
+}
+
+/// Answers a single HTTP request and closes the connection.
+async fn handle_scrape<S>(mut stream: S, state: Arc<RwLock<VacuumState>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => {
+            tracing::debug!("Dropping metrics request: {}", e);
+            return;
+        }
+        Err(_) => return,
+    };
+
+    let mut parts = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let response = match (method, path) {
+        ("GET", "/metrics") => {
+            let body = render(&*state.read().await);
+            http_response("200 OK", CONTENT_TYPE, &body)
+        }
+        ("GET", _) => http_response("404 Not Found", "text/plain", "Not found\n"),
+        _ => http_response("405 Method Not Allowed", "text/plain", "Only GET is supported\n"),
+    };
+
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        tracing::debug!("Failed to answer metrics request: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request_head<S: AsyncRead + Unpin>(stream: &mut S) -> Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Connection closed before the request was complete"));
+        }
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > MAX_REQUEST_BYTES {
+            return Err(anyhow::anyhow!("Request head exceeds {} bytes", MAX_REQUEST_BYTES));
+        }
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )
+}
+
+/// Renders the collected state in the OpenMetrics text format.
+pub fn render(state: &VacuumState) -> String {
+    let mut out = String::new();
+    let system = &state.system_info;
+
+    family(&mut out, "vacuum_cpu_load_percent", "gauge", "CPU load across all cores");
+    sample(&mut out, "vacuum_cpu_load_percent", &[], system.cpu_load_percent);
+
+    family(&mut out, "vacuum_memory_used_bytes", "gauge", "RAM in use");
+    sample(&mut out, "vacuum_memory_used_bytes", &[], system.ram_used_bytes as f64);
+    family(&mut out, "vacuum_memory_total_bytes", "gauge", "Installed RAM");
+    sample(&mut out, "vacuum_memory_total_bytes", &[], system.ram_total_bytes as f64);
+
+    family(&mut out, "vacuum_disk_used_bytes", "gauge", "Space used per mounted filesystem");
+    for disk in &state.storage_info {
+        let labels = [("device", disk.device.as_str()), ("mountpoint", disk.mountpoint.as_str())];
+        sample(&mut out, "vacuum_disk_used_bytes", &labels, disk.used_bytes as f64);
+    }
+    family(&mut out, "vacuum_disk_total_bytes", "gauge", "Size of each mounted filesystem");
+    for disk in &state.storage_info {
+        let labels = [("device", disk.device.as_str()), ("mountpoint", disk.mountpoint.as_str())];
+        sample(&mut out, "vacuum_disk_total_bytes", &labels, disk.total_bytes as f64);
+    }
+
+    let traffic = &state.network_traffic;
+    let interface = [("interface", traffic.interface.as_str())];
+    // The collector reports KiB/s; metrics use base units
+    family(&mut out, "vacuum_network_receive_bytes_per_second", "gauge", "Receive rate of the default route interface in bytes per second");
+    sample(&mut out, "vacuum_network_receive_bytes_per_second", &interface, traffic.rx_kbps * 1024.0);
+    family(&mut out, "vacuum_network_transmit_bytes_per_second", "gauge", "Transmit rate of the default route interface in bytes per second");
+    sample(&mut out, "vacuum_network_transmit_bytes_per_second", &interface, traffic.tx_kbps * 1024.0);
+
+    family(&mut out, "vacuum_volume_percent", "gauge", "Default sink volume");
+    sample(&mut out, "vacuum_volume_percent", &[], state.volume_state.level_percent as f64);
+    family(&mut out, "vacuum_volume_muted", "gauge", "Whether the default sink is muted");
+    sample(&mut out, "vacuum_volume_muted", &[], if state.volume_state.muted { 1.0 } else { 0.0 });
+
+    family(&mut out, "vacuum_section_up", "gauge", "Whether the last refresh of a state section succeeded");
+    for (section, meta) in &state.sections {
+        let labels = [("section", section.name())];
+        sample(&mut out, "vacuum_section_up", &labels, if meta.last_error.is_none() { 1.0 } else { 0.0 });
+    }
+    family(&mut out, "vacuum_section_last_updated_seconds", "gauge", "Unix time of the last successful refresh of a state section");
+    for (section, meta) in &state.sections {
+        if let Some(updated_ms) = meta.last_updated_ms {
+            let labels = [("section", section.name())];
+            sample(&mut out, "vacuum_section_last_updated_seconds", &labels, updated_ms as f64 / 1000.0);
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels.iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+            .collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{DiskInfo, StateSection};
+
+    #[test]
+    fn test_render_is_openmetrics() {
+        let mut state = VacuumState::default();
+        state.storage_info.push(DiskInfo {
+            device: "/dev/nvme0n1p2".to_string(),
+            mountpoint: "/home/\"quoted\"".to_string(),
+            fs_type: "ext4".to_string(),
+            used_bytes: 1024,
+            total_bytes: 4096,
+        });
+        state.mark_failed(StateSection::Toggles, "bluetoothctl", "not found".to_string());
+        state.network_traffic.interface = "wlan0".to_string();
+        state.network_traffic.rx_kbps = 2.5;
+
+        let text = render(&state);
+        assert!(text.contains("# TYPE vacuum_disk_used_bytes gauge\n"));
+        assert!(text.contains("vacuum_disk_used_bytes{device=\"/dev/nvme0n1p2\",mountpoint=\"/home/\\\"quoted\\\"\"} 1024\n"));
+        assert!(text.contains("vacuum_section_up{section=\"toggles\"} 0\n"));
+        assert!(text.contains("vacuum_network_receive_bytes_per_second{interface=\"wlan0\"} 2560\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_only_stale_sockets_are_removed() {
+        let dir = std::env::temp_dir().join(format!("vacuum-metrics-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("not-a-socket");
+        std::fs::write(&file, "keep me").unwrap();
+        assert!(remove_stale_socket(&file).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep me");
+
+        let socket = dir.join("metrics.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        remove_stale_socket(&socket).unwrap();
+        assert!(!socket.exists());
+        remove_stale_socket(&socket).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_listen_must_be_loopback_or_unix() {
+        assert_eq!(MetricsListen::parse("127.0.0.1:9477").unwrap(), MetricsListen::Tcp("127.0.0.1:9477".parse().unwrap()));
+        assert_eq!(MetricsListen::parse("unix:/run/metrics.sock").unwrap(), MetricsListen::Unix("/run/metrics.sock".into()));
+        assert!(MetricsListen::parse("0.0.0.0:9477").is_err());
+        assert!(MetricsListen::parse("unix:").is_err());
+    }
+}
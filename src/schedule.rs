@@ -0,0 +1,145 @@
+use crate::state::{PowerAction, ScheduledAction, unix_time_ms};
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// A schedule restored after a restart still runs if it came due at most
+/// this long ago; anything older is dropped rather than fired by surprise.
+const OVERDUE_GRACE: Duration = Duration::from_secs(60);
+
+/// When a scheduled power action should run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleTime {
+    /// Unix time in seconds, e.g. today at 18:00
+    At(u64),
+    /// Seconds from now
+    AfterSecs(u64),
+}
+
+impl ScheduleTime {
+    /// Unix time in milliseconds the action is due.
+    pub fn due_ms(&self, now_ms: u64) -> Result<u64> {
+        let out_of_range = || anyhow::anyhow!("Scheduled time is too far in the future");
+        match *self {
+            ScheduleTime::At(secs) => {
+                let due_ms = secs.checked_mul(1000).ok_or_else(out_of_range)?;
+                if due_ms <= now_ms {
+                    return Err(anyhow::anyhow!("Scheduled time {} is in the past", secs));
+                }
+                Ok(due_ms)
+            }
+            ScheduleTime::AfterSecs(secs) => secs.checked_mul(1000)
+                .and_then(|delay_ms| now_ms.checked_add(delay_ms))
+                .ok_or_else(out_of_range),
+        }
+    }
+}
+
+/// The one pending shutdown or reboot, mirrored to disk so it survives a
+/// daemon restart. Setting a new schedule replaces the previous one.
+#[derive(Clone)]
+pub struct PowerSchedule {
+    path: PathBuf,
+    current: watch::Sender<Option<ScheduledAction>>,
+}
+
+impl PowerSchedule {
+    /// Restores the schedule saved at `path`, if any.
+    pub fn load(path: PathBuf) -> Self {
+        let restored = match read_schedule(&path) {
+            Ok(Some(scheduled)) if scheduled.due_ms.saturating_add(OVERDUE_GRACE.as_millis() as u64) < unix_time_ms() => {
+                tracing::warn!("Dropping scheduled {:?} that was due while the daemon was down", scheduled.action);
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+            Ok(Some(scheduled)) => {
+                tracing::info!("Restored scheduled {:?}", scheduled.action);
+                Some(scheduled)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable power schedule: {:#}", e);
+                None
+            }
+        };
+
+        Self { path, current: watch::channel(restored).0 }
+    }
+
+    pub fn current(&self) -> Option<ScheduledAction> {
+        self.current.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<ScheduledAction>> {
+        self.current.subscribe()
+    }
+
+    pub fn schedule(&self, action: PowerAction, when: &ScheduleTime) -> Result<ScheduledAction> {
+        let now_ms = unix_time_ms();
+        let scheduled = ScheduledAction::new(action, when.due_ms(now_ms)?, now_ms);
+        let json = serde_json::to_string(&scheduled)?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Failed to save power schedule to {:?}", self.path))?;
+        self.current.send_replace(Some(scheduled.clone()));
+        Ok(scheduled)
+    }
+
+    /// Clears the schedule and returns what was pending.
+    pub fn cancel(&self) -> Option<ScheduledAction> {
+        if let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::warn!("Failed to remove power schedule {:?}: {}", self.path, e);
+        }
+        self.current.send_replace(None)
+    }
+}
+
+fn read_schedule(path: &Path) -> Result<Option<ScheduledAction>> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    let scheduled = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse {:?}", path))?;
+    Ok(Some(scheduled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_survives_reload_unless_long_overdue() {
+        let path = std::env::temp_dir().join(format!("vacuum-schedule-test-{}.json", std::process::id()));
+
+        let schedule = PowerSchedule::load(path.clone());
+        assert!(schedule.schedule(PowerAction::Reboot, &ScheduleTime::At(1)).is_err());
+        let scheduled = schedule.schedule(PowerAction::Reboot, &ScheduleTime::AfterSecs(3600)).unwrap();
+        assert_eq!(PowerSchedule::load(path.clone()).current(), Some(scheduled.clone()));
+
+        let overdue = ScheduledAction::new(PowerAction::Shutdown, unix_time_ms() - 3_600_000, unix_time_ms());
+        std::fs::write(&path, serde_json::to_string(&overdue).unwrap()).unwrap();
+        assert_eq!(PowerSchedule::load(path.clone()).current(), None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_out_of_range_times_are_rejected() {
+        assert!(ScheduleTime::AfterSecs(u64::MAX).due_ms(1_000).is_err());
+        assert!(ScheduleTime::AfterSecs(u64::MAX / 1000).due_ms(1_000).is_err());
+        assert!(ScheduleTime::At(u64::MAX).due_ms(1_000).is_err());
+
+        // Schedules saved before times were checked still load
+        let path = std::env::temp_dir().join(format!("vacuum-schedule-range-test-{}.json", std::process::id()));
+        let far = ScheduledAction::new(PowerAction::Shutdown, u64::MAX, unix_time_ms());
+        std::fs::write(&path, serde_json::to_string(&far).unwrap()).unwrap();
+        let restored = PowerSchedule::load(path.clone()).current();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored, Some(far));
+    }
+}
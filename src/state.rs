@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 use crate::cava::AudioVisualizerData;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct VacuumState {
     pub user_info: UserInfo,
     pub system_info: SystemInfo,
@@ -14,9 +15,28 @@ pub struct VacuumState {
     pub weather_info: WeatherInfo,
     pub launcher_shortcuts: LauncherShortcuts,
     pub toggles: Toggles,
+    #[serde(default)]
+    pub overlay: OverlayState,
+    /// Shutdown or reboot set up with `ScheduleShutdown`/`ScheduleReboot`
+    #[serde(default)]
+    pub scheduled_action: Option<ScheduledAction>,
+    /// Freshness and error details for each section that has been touched
+    #[serde(default)]
+    pub sections: BTreeMap<StateSection, SectionMeta>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Where a section's data came from and whether refreshing it works.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SectionMeta {
+    /// Unix time in milliseconds of the last successful refresh
+    pub last_updated_ms: Option<u64>,
+    /// Why the most recent refresh failed; cleared by the next success
+    pub last_error: Option<String>,
+    /// Tool or command that produced the data, e.g. `nmcli` or `SetVolume`
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UserInfo {
     pub username: String,
     pub display_name: Option<String>,
@@ -25,7 +45,7 @@ pub struct UserInfo {
     pub avatar_path: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SystemInfo {
     pub os_name: String,
     pub hostname: String,
@@ -41,7 +61,7 @@ pub struct SystemInfo {
     pub gpu_vram_total_bytes: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DiskInfo {
     pub device: String,
     pub mountpoint: String,
@@ -50,7 +70,7 @@ pub struct DiskInfo {
     pub total_bytes: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NetworkStatus {
     pub interface: String,
     pub ip_address: String,
@@ -58,14 +78,14 @@ pub struct NetworkStatus {
     pub link_state: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NetworkTraffic {
     pub interface: String,
     pub rx_kbps: f64,
     pub tx_kbps: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AudioStatus {
     pub source_name: String,
     pub track_title: String,
@@ -73,13 +93,13 @@ pub struct AudioStatus {
     pub playing: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VolumeState {
     pub level_percent: u8,
     pub muted: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WeatherInfo {
     pub location_display: String,
     pub temperature_c: i32,
@@ -87,13 +107,13 @@ pub struct WeatherInfo {
     pub icon_name: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LauncherShortcuts {
     pub left_links: Vec<LinkButton>,
     pub launcher_command: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LinkButton {
     pub label: String,
     pub url: String,
@@ -101,31 +121,210 @@ pub struct LinkButton {
     pub icon_path: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Toggles {
     pub wifi_enabled: bool,
     pub vpn_connected: bool,
     pub bluetooth_enabled: bool,
 }
 
-impl Default for VacuumState {
-    fn default() -> Self {
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OverlayState {
+    pub visible: bool,
+    /// Unix time in milliseconds of the last visibility change, 0 if never changed
+    pub changed_at_ms: u64,
+    /// Frontends registered through `RegisterOverlay` on open connections
+    pub frontends: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerAction {
+    Shutdown,
+    Reboot,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledAction {
+    pub action: PowerAction,
+    /// Unix time in milliseconds the action runs
+    pub due_ms: u64,
+    /// Whole seconds left, refreshed every second for countdowns
+    pub remaining_secs: u64,
+}
+
+impl ScheduledAction {
+    pub fn new(action: PowerAction, due_ms: u64, now_ms: u64) -> Self {
         Self {
-            user_info: UserInfo::default(),
-            system_info: SystemInfo::default(),
-            storage_info: Vec::new(),
-            network_status: NetworkStatus::default(),
-            network_traffic: NetworkTraffic::default(),
-            audio_status: AudioStatus::default(),
-            volume_state: VolumeState::default(),
-            audio_visualizer: AudioVisualizerData::default(),
-            weather_info: WeatherInfo::default(),
-            launcher_shortcuts: LauncherShortcuts::default(),
-            toggles: Toggles::default(),
+            action,
+            due_ms,
+            remaining_secs: due_ms.saturating_sub(now_ms).div_ceil(1000),
         }
     }
 }
 
+/// Independently updated parts of `VacuumState` that clients can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum StateSection {
+    UserInfo,
+    SystemInfo,
+    StorageInfo,
+    NetworkStatus,
+    NetworkTraffic,
+    AudioStatus,
+    VolumeState,
+    WeatherInfo,
+    LauncherShortcuts,
+    Toggles,
+    Overlay,
+    ScheduledAction,
+}
+
+impl StateSection {
+    pub const ALL: [StateSection; 12] = [
+        StateSection::UserInfo,
+        StateSection::SystemInfo,
+        StateSection::StorageInfo,
+        StateSection::NetworkStatus,
+        StateSection::NetworkTraffic,
+        StateSection::AudioStatus,
+        StateSection::VolumeState,
+        StateSection::WeatherInfo,
+        StateSection::LauncherShortcuts,
+        StateSection::Toggles,
+        StateSection::Overlay,
+        StateSection::ScheduledAction,
+    ];
+
+    /// Snake-case name, as used for the field in `VacuumState`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StateSection::UserInfo => "user_info",
+            StateSection::SystemInfo => "system_info",
+            StateSection::StorageInfo => "storage_info",
+            StateSection::NetworkStatus => "network_status",
+            StateSection::NetworkTraffic => "network_traffic",
+            StateSection::AudioStatus => "audio_status",
+            StateSection::VolumeState => "volume_state",
+            StateSection::WeatherInfo => "weather_info",
+            StateSection::LauncherShortcuts => "launcher_shortcuts",
+            StateSection::Toggles => "toggles",
+            StateSection::Overlay => "overlay",
+            StateSection::ScheduledAction => "scheduled_action",
+        }
+    }
+
+    /// Accepts either the snake-case name or the variant name, e.g.
+    /// `system_info` or `SystemInfo`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let wanted = name.replace('_', "");
+        Self::ALL.into_iter().find(|section| section.name().replace('_', "").eq_ignore_ascii_case(&wanted))
+    }
+}
+
+/// New contents of a single state section.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StateUpdate {
+    UserInfo(UserInfo),
+    SystemInfo(SystemInfo),
+    StorageInfo(Vec<DiskInfo>),
+    NetworkStatus(NetworkStatus),
+    NetworkTraffic(NetworkTraffic),
+    AudioStatus(AudioStatus),
+    VolumeState(VolumeState),
+    WeatherInfo(WeatherInfo),
+    LauncherShortcuts(LauncherShortcuts),
+    Toggles(Toggles),
+    Overlay(OverlayState),
+    ScheduledAction(Option<ScheduledAction>),
+}
+
+impl StateUpdate {
+    pub fn section(&self) -> StateSection {
+        match self {
+            StateUpdate::UserInfo(_) => StateSection::UserInfo,
+            StateUpdate::SystemInfo(_) => StateSection::SystemInfo,
+            StateUpdate::StorageInfo(_) => StateSection::StorageInfo,
+            StateUpdate::NetworkStatus(_) => StateSection::NetworkStatus,
+            StateUpdate::NetworkTraffic(_) => StateSection::NetworkTraffic,
+            StateUpdate::AudioStatus(_) => StateSection::AudioStatus,
+            StateUpdate::VolumeState(_) => StateSection::VolumeState,
+            StateUpdate::WeatherInfo(_) => StateSection::WeatherInfo,
+            StateUpdate::LauncherShortcuts(_) => StateSection::LauncherShortcuts,
+            StateUpdate::Toggles(_) => StateSection::Toggles,
+            StateUpdate::Overlay(_) => StateSection::Overlay,
+            StateUpdate::ScheduledAction(_) => StateSection::ScheduledAction,
+        }
+    }
+}
+
+impl VacuumState {
+    /// Snapshot of one section, used to prime new subscribers.
+    pub fn section(&self, section: StateSection) -> StateUpdate {
+        match section {
+            StateSection::UserInfo => StateUpdate::UserInfo(self.user_info.clone()),
+            StateSection::SystemInfo => StateUpdate::SystemInfo(self.system_info.clone()),
+            StateSection::StorageInfo => StateUpdate::StorageInfo(self.storage_info.clone()),
+            StateSection::NetworkStatus => StateUpdate::NetworkStatus(self.network_status.clone()),
+            StateSection::NetworkTraffic => StateUpdate::NetworkTraffic(self.network_traffic.clone()),
+            StateSection::AudioStatus => StateUpdate::AudioStatus(self.audio_status.clone()),
+            StateSection::VolumeState => StateUpdate::VolumeState(self.volume_state.clone()),
+            StateSection::WeatherInfo => StateUpdate::WeatherInfo(self.weather_info.clone()),
+            StateSection::LauncherShortcuts => StateUpdate::LauncherShortcuts(self.launcher_shortcuts.clone()),
+            StateSection::Toggles => StateUpdate::Toggles(self.toggles.clone()),
+            StateSection::Overlay => StateUpdate::Overlay(self.overlay.clone()),
+            StateSection::ScheduledAction => StateUpdate::ScheduledAction(self.scheduled_action.clone()),
+        }
+    }
+
+    /// Records a successful refresh of `section`, whether or not its value changed.
+    pub fn mark_updated(&mut self, section: StateSection, source: &str) {
+        let meta = self.sections.entry(section).or_default();
+        meta.last_updated_ms = Some(unix_time_ms());
+        meta.last_error = None;
+        meta.source = Some(source.to_string());
+    }
+
+    /// Records a failed refresh; the section keeps its previous value and timestamp.
+    pub fn mark_failed(&mut self, section: StateSection, source: &str, error: String) {
+        let meta = self.sections.entry(section).or_default();
+        meta.last_error = Some(error);
+        meta.source = Some(source.to_string());
+    }
+
+    /// Stores `update` and returns whether the section actually changed.
+    pub fn apply(&mut self, update: &StateUpdate) -> bool {
+        fn replace<T: PartialEq + Clone>(slot: &mut T, value: &T) -> bool {
+            if slot == value {
+                return false;
+            }
+            *slot = value.clone();
+            true
+        }
+
+        match update {
+            StateUpdate::UserInfo(value) => replace(&mut self.user_info, value),
+            StateUpdate::SystemInfo(value) => replace(&mut self.system_info, value),
+            StateUpdate::StorageInfo(value) => replace(&mut self.storage_info, value),
+            StateUpdate::NetworkStatus(value) => replace(&mut self.network_status, value),
+            StateUpdate::NetworkTraffic(value) => replace(&mut self.network_traffic, value),
+            StateUpdate::AudioStatus(value) => replace(&mut self.audio_status, value),
+            StateUpdate::VolumeState(value) => replace(&mut self.volume_state, value),
+            StateUpdate::WeatherInfo(value) => replace(&mut self.weather_info, value),
+            StateUpdate::LauncherShortcuts(value) => replace(&mut self.launcher_shortcuts, value),
+            StateUpdate::Toggles(value) => replace(&mut self.toggles, value),
+            StateUpdate::Overlay(value) => replace(&mut self.overlay, value),
+            StateUpdate::ScheduledAction(value) => replace(&mut self.scheduled_action, value),
+        }
+    }
+}
+
+pub fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 impl Default for UserInfo {
     fn default() -> Self {
         Self {
@@ -235,14 +434,4 @@ impl Default for LauncherShortcuts {
             launcher_command: "fuzzel".to_string(),
         }
     }
-}
-
-impl Default for Toggles {
-    fn default() -> Self {
-        Self {
-            wifi_enabled: false,
-            vpn_connected: false,
-            bluetooth_enabled: false,
-        }
-    }
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use anyhow::{Result, Context};
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Minimal implementations of the systemd socket activation and sd_notify
+// protocols, see sd_listen_fds(3) and sd_notify(3).
+
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// `LISTEN_PID` and `LISTEN_FDS`, once `take_activation_env` has moved them here.
+static ACTIVATION: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+/// Moves the socket activation variables out of the environment, so child
+/// processes don't inherit them. Call it first thing in `main`: changing the
+/// environment is only sound while the process has a single thread.
+pub fn take_activation_env() {
+    let listen_pid = std::env::var("LISTEN_PID").ok();
+    let listen_fds = std::env::var("LISTEN_FDS").ok();
+    // SAFETY: runs in `main` before the tokio runtime or any other thread starts
+    unsafe {
+        std::env::remove_var("LISTEN_PID");
+        std::env::remove_var("LISTEN_FDS");
+        std::env::remove_var("LISTEN_FDNAMES");
+    }
+    if let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) {
+        *ACTIVATION.lock().unwrap() = Some((listen_pid, listen_fds));
+    }
+}
+
+/// Takes the listening socket passed in by systemd socket activation, if
+/// `take_activation_env` found one. Only the first call gets it.
+pub fn listen_socket() -> Result<Option<UnixListener>> {
+    let Some((listen_pid, listen_fds)) = ACTIVATION.lock().unwrap().take() else {
+        return Ok(None);
+    };
+    if listen_pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(None);
+    }
+
+    let count: RawFd = listen_fds.parse().context("Invalid LISTEN_FDS")?;
+    if count != 1 {
+        return Err(anyhow::anyhow!("Expected exactly one socket from systemd, got {}", count));
+    }
+
+    let fd = SD_LISTEN_FDS_START;
+    nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC))
+        .context("Passed socket is not a valid file descriptor")?;
+    // SAFETY: systemd hands us ownership of the descriptors starting at SD_LISTEN_FDS_START
+    let listener = unsafe { UnixListener::from_raw_fd(fd) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+/// Sends a state string such as `READY=1` to the service manager.
+/// Returns `Ok(false)` when not running under a manager that asked for it.
+pub fn notify(state: &str) -> Result<bool> {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+
+    // A leading '@' names a socket in the abstract namespace
+    let address = match path.as_encoded_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &address)
+        .with_context(|| format!("Failed to notify service manager at {:?}", path))?;
+    Ok(true)
+}
+
+/// How often to send `WATCHDOG=1`, if the service has a watchdog enabled.
+/// Pings go out at half the configured timeout, as sd_watchdog_enabled(3) advises.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec / 2))
+}
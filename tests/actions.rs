@@ -0,0 +1,90 @@
+mod common;
+
+use common::TestDaemon;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use vacuum_launcher::audit::AuditRecord;
+use vacuum_launcher::config::Config;
+use vacuum_launcher::daemon::{IpcCommand, IpcResponse};
+
+/// Starts a daemon whose `pactl` hangs on `set-sink-volume` after recording its PID.
+fn spawn_with_hanging_pactl(name: &str) -> (TestDaemon, PathBuf) {
+    let mut pid_file = PathBuf::new();
+    let daemon = TestDaemon::spawn_with(name, |root, mut command| {
+        pid_file = root.join("pactl.pid");
+        let script = format!(
+            "#!/bin/sh\nif [ \"$1\" = set-sink-volume ]; then echo $$ > {:?}; exec sleep 60; fi\nexit 1\n",
+            pid_file
+        );
+        common::fake_bin(root, &mut command, "pactl", &script);
+
+        let mut config = Config::default();
+        config.actions.timeouts.insert("SetVolume".to_string(), 1);
+        common::write_config(root, &config);
+        command
+    });
+    (daemon, pid_file)
+}
+
+fn read_pid(daemon: &TestDaemon, pid_file: &Path) -> u32 {
+    daemon.wait_until(Duration::from_secs(10), || {
+        std::fs::read_to_string(pid_file).is_ok_and(|pid| pid.ends_with('\n'))
+    });
+    std::fs::read_to_string(pid_file).unwrap().trim().parse().unwrap()
+}
+
+/// Live as opposed to gone or a zombie waiting to be reaped.
+fn is_running(pid: u32) -> bool {
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => stat.rsplit(") ").next().is_some_and(|rest| !rest.starts_with('Z')),
+        Err(_) => false,
+    }
+}
+
+#[tokio::test]
+async fn test_hung_action_times_out_and_is_killed() {
+    let (daemon, pid_file) = spawn_with_hanging_pactl("action-timeout");
+    let client = daemon.client().await;
+
+    let response = client.request(IpcCommand::SetVolume(40)).await.unwrap();
+    match response {
+        IpcResponse::ActionTimedOut { command, timeout_ms } => {
+            assert_eq!(command, "SetVolume");
+            assert_eq!(timeout_ms, 1000);
+        }
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+
+    let pid = read_pid(&daemon, &pid_file);
+    daemon.wait_until(Duration::from_secs(5), || !is_running(pid));
+
+    // The daemon keeps serving while and after the action hangs
+    client.hello("actions-test").await.unwrap();
+}
+
+// The request runs on another worker while this test blocks polling for the PID
+#[tokio::test(flavor = "multi_thread")]
+async fn test_action_cancelled_when_client_disconnects() {
+    let (daemon, pid_file) = spawn_with_hanging_pactl("action-cancel");
+    let client = daemon.client().await;
+
+    let request = tokio::spawn(async move {
+        let _ = client.request(IpcCommand::SetVolume(40)).await;
+    });
+    let pid = read_pid(&daemon, &pid_file);
+    assert!(is_running(pid));
+
+    // Dropping the request drops the client and closes its connection
+    request.abort();
+    let _ = request.await;
+    daemon.wait_until(Duration::from_millis(900), || !is_running(pid));
+
+    // The action was started, so it must be on record even though it never answered
+    let output = daemon.cli(&["--audit-log", "--command", "SetVolume", "--json"]);
+    let records: Vec<AuditRecord> = String::from_utf8(output.stdout).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert_eq!(records[0].result, "cancelled");
+}
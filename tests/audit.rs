@@ -0,0 +1,27 @@
+mod common;
+
+use common::TestDaemon;
+use vacuum_launcher::audit::AuditRecord;
+use vacuum_launcher::daemon::{IpcCommand, IpcResponse};
+
+#[tokio::test]
+async fn test_actions_are_audited_with_caller_identity() {
+    let daemon = TestDaemon::spawn_with_fake_bin("audit", "pactl", common::FAILING_PACTL);
+    let client = daemon.client().await;
+
+    assert!(matches!(client.request(IpcCommand::ToggleMute).await.unwrap(), IpcResponse::Error(_)));
+    assert!(matches!(client.request(IpcCommand::GetState).await.unwrap(), IpcResponse::State(_)));
+
+    let output = daemon.cli(&["--audit-log", "--since", "1h", "--json"]);
+    assert!(output.status.success());
+
+    let records: Vec<AuditRecord> = String::from_utf8(output.stdout).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1, "reads must not be audited: {:?}", records);
+    assert_eq!(records[0].command, "ToggleMute");
+    assert_eq!(records[0].result, "error");
+    assert_eq!(records[0].uid, nix::unistd::Uid::current().as_raw());
+    assert_eq!(records[0].pid, Some(std::process::id() as i32));
+}
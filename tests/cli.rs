@@ -0,0 +1,76 @@
+mod common;
+
+use common::TestDaemon;
+use vacuum_launcher::daemon::IpcResponse;
+
+/// Starts a daemon whose `pactl` always fails, so no test touches the real mixer.
+fn spawn_with_failing_pactl(name: &str) -> TestDaemon {
+    TestDaemon::spawn_with_fake_bin(name, "pactl", common::FAILING_PACTL)
+}
+
+#[test]
+fn test_exit_codes_follow_responses() {
+    let daemon = spawn_with_failing_pactl("cli-exit-codes");
+
+    let output = daemon.cli(&["volume", "set", "40", "--json"]);
+    assert_eq!(output.status.code(), Some(1));
+    let response: IpcResponse = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(matches!(response, IpcResponse::Error(_)));
+
+    assert_eq!(daemon.cli(&["volume", "set", "140"]).status.code(), Some(2));
+    assert_eq!(daemon.cli(&["overlay", "show"]).status.code(), Some(0));
+
+    let output = daemon.cli(&["overlay", "toggle"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Overlay hidden");
+}
+
+#[test]
+fn test_power_action_without_terminal_leaves_token() {
+    let daemon = spawn_with_failing_pactl("cli-power");
+
+    // stdin is not a terminal and --yes is absent, so nothing may run
+    let output = daemon.cli(&["reboot", "--json"]);
+    assert_eq!(output.status.code(), Some(5));
+    let token = match serde_json::from_slice(&output.stdout).unwrap() {
+        IpcResponse::ConfirmationRequired { token, .. } => token,
+        other => panic!("expected a confirmation request, got {:?}", other),
+    };
+
+    assert_eq!(daemon.cli(&["cancel", &token]).status.code(), Some(0));
+    assert_eq!(daemon.cli(&["confirm", &token]).status.code(), Some(1));
+}
+
+#[test]
+fn test_unreachable_daemon() {
+    let daemon = spawn_with_failing_pactl("cli-unreachable");
+    std::fs::remove_file(daemon.socket_path()).unwrap();
+    std::fs::remove_file(daemon.config_path()).unwrap();
+    assert_eq!(daemon.cli(&["state"]).status.code(), Some(3));
+    assert_eq!(daemon.cli(&["--toggle"]).status.code(), Some(3));
+
+    // Trying to connect leaves no config file behind
+    assert!(!daemon.config_path().exists());
+}
+
+#[test]
+fn test_status_reports_process_and_loops() {
+    let daemon = spawn_with_failing_pactl("cli-status");
+
+    let output = daemon.cli(&["status", "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let status = match serde_json::from_slice(&output.stdout).unwrap() {
+        IpcResponse::Status(status) => status,
+        other => panic!("expected a status, got {:?}", other),
+    };
+    assert_eq!(status.pid, daemon.child.id());
+    assert_eq!(status.socket_path, daemon.socket_path());
+    assert_eq!(status.config_path, daemon.config_path());
+    assert_eq!(status.clients, 1);
+    assert!(status.cava == "cava_enabled" || status.cava == "cava_disabled");
+    let names: Vec<_> = status.loops.iter().map(|health| health.name.as_str()).collect();
+    assert_eq!(names, ["audio", "network", "system", "weather"]);
+
+    let output = daemon.cli(&["status"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("(pid {})", daemon.child.id())));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::TestDaemon;
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use std::process::Stdio;
+use std::time::Duration;
+use vacuum_launcher::client::{ConnectOptions, IpcClient};
+use vacuum_launcher::config::Config;
+use vacuum_launcher::daemon::{IpcCommand, IpcResponse};
+use vacuum_launcher::state::StateSection;
+
+#[tokio::test]
+async fn test_subscription_survives_daemon_restart() {
+    let mut daemon = TestDaemon::spawn("client-reconnect");
+    let client = IpcClient::connect_to(&daemon.socket_path(), ConnectOptions::default()).await.unwrap();
+    let mut updates = client.subscribe(vec![StateSection::Toggles]).await.unwrap();
+    let snapshot = tokio::time::timeout(Duration::from_secs(10), updates.recv()).await.unwrap();
+    assert_eq!(snapshot.map(|update| update.section()), Some(StateSection::Toggles));
+
+    daemon.child.kill().unwrap();
+    daemon.child.wait().unwrap();
+    let mut restarted = daemon.command(&["--daemon"]).stdout(Stdio::null()).stderr(Stdio::null()).spawn().unwrap();
+
+    // The reopened subscription starts over with a fresh snapshot
+    let snapshot = tokio::time::timeout(Duration::from_secs(20), updates.recv()).await.expect("no update after restart");
+    assert_eq!(snapshot.map(|update| update.section()), Some(StateSection::Toggles));
+    assert!(matches!(client.request(IpcCommand::Status).await.unwrap(), IpcResponse::Status(_)));
+
+    restarted.kill().unwrap();
+    restarted.wait().unwrap();
+}
+
+#[tokio::test]
+async fn test_requests_reconnect_after_daemon_restart() {
+    let mut daemon = TestDaemon::spawn("client-request-reconnect");
+    let options = ConnectOptions { retry_for: Duration::from_secs(20), ..ConnectOptions::default() };
+    let client = IpcClient::connect_to(&daemon.socket_path(), options).await.unwrap();
+    assert!(matches!(client.request(IpcCommand::Status).await.unwrap(), IpcResponse::Status(_)));
+
+    daemon.child.kill().unwrap();
+    daemon.child.wait().unwrap();
+    // Let the client notice the connection is gone
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let mut restarted = daemon.command(&["--daemon"]).stdout(Stdio::null()).stderr(Stdio::null()).spawn().unwrap();
+
+    // With no subscriptions to resume, the next request reconnects on its own
+    assert!(matches!(client.request(IpcCommand::Status).await.unwrap(), IpcResponse::Status(_)));
+
+    restarted.kill().unwrap();
+    restarted.wait().unwrap();
+}
+
+#[test]
+fn test_cli_autostarts_the_daemon() {
+    let mut daemon = TestDaemon::spawn("client-autostart");
+    daemon.child.kill().unwrap();
+    daemon.child.wait().unwrap();
+
+    let mut config = Config::default();
+    config.ipc.autostart = true;
+    config.ipc.connect_timeout_secs = 10;
+    common::write_config(&daemon.root, &config);
+
+    let output = daemon.cli(&["status", "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let status = match serde_json::from_slice(&output.stdout).unwrap() {
+        IpcResponse::Status(status) => status,
+        other => panic!("expected a status, got {:?}", other),
+    };
+    assert_ne!(status.pid, daemon.child.id());
+
+    kill(Pid::from_raw(status.pid as i32), Signal::SIGTERM).unwrap();
+    daemon.wait_until(Duration::from_secs(10), || !daemon.pid_path().exists());
+}
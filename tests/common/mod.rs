@@ -0,0 +1,155 @@
+#![allow(dead_code)]
+
+use nix::sys::signal::{Signal, kill};
+use std::os::unix::fs::PermissionsExt;
+use nix::unistd::Pid;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::time::{Duration, Instant};
+use tokio::net::UnixStream;
+use vacuum_launcher::client::IpcClient;
+use vacuum_launcher::config::Config;
+use vacuum_launcher::ipc::DEFAULT_MAX_MESSAGE_BYTES;
+
+/// Writes `config` where a daemon spawned under `root` will load it from.
+pub fn write_config(root: &Path, config: &Config) {
+    let config_dir = root.join("config/vacuum");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), toml::to_string_pretty(config).unwrap()).unwrap();
+}
+
+/// A `pactl` that always fails, so no test touches the real mixer.
+pub const FAILING_PACTL: &str = "#!/bin/sh\necho 'no sink' >&2\nexit 1\n";
+
+/// Installs `script` as `program` under `root` and puts it first on the
+/// PATH of `command`.
+pub fn fake_bin(root: &Path, command: &mut Command, program: &str, script: &str) {
+    let bin = root.join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    std::fs::write(bin.join(program), script).unwrap();
+    std::fs::set_permissions(bin.join(program), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default());
+    command.env("PATH", path);
+}
+
+/// A daemon running against its own runtime, config and state directories.
+pub struct TestDaemon {
+    pub root: PathBuf,
+    pub child: Child,
+}
+
+impl TestDaemon {
+    pub fn spawn(name: &str) -> Self {
+        Self::spawn_with(name, |_, command| command)
+    }
+
+    /// Spawns the daemon with `script` standing in for `program`.
+    pub fn spawn_with_fake_bin(name: &str, program: &str, script: &str) -> Self {
+        Self::spawn_with(name, |root, mut command| {
+            fake_bin(root, &mut command, program, script);
+            command
+        })
+    }
+
+    /// Spawns the daemon through a customised command. `build` receives the
+    /// test root and the default `--daemon` invocation, and returns the
+    /// command to run; the isolated environment is applied on top.
+    pub fn spawn_with(name: &str, build: impl FnOnce(&Path, Command) -> Command) -> Self {
+        let root = std::env::temp_dir().join(format!("vacuum-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("runtime")).unwrap();
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_vacuum-launcher"));
+        // Keep off the developer's session bus and daemon unless a test brings its own
+        command.arg("--daemon")
+            .env_remove("DBUS_SESSION_BUS_ADDRESS")
+            .env_remove("VACUUM_SOCKET")
+            .env_remove("VACUUM_CONFIG");
+        let child = build(&root, command)
+            .env("HOME", &root)
+            .env("XDG_RUNTIME_DIR", root.join("runtime"))
+            .env("XDG_CONFIG_HOME", root.join("config"))
+            .env("XDG_STATE_HOME", root.join("state"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let daemon = Self { root, child };
+        daemon.wait_until(Duration::from_secs(10), || {
+            std::os::unix::net::UnixStream::connect(daemon.socket_path()).is_ok()
+        });
+        daemon
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        self.root.join("runtime/vacuum-launcher/vacuum-launcher.sock")
+    }
+
+    pub fn pid_path(&self) -> PathBuf {
+        self.root.join("runtime/vacuum-launcher/vacuum-launcher.pid")
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.root.join("config/vacuum/config.toml")
+    }
+
+    pub async fn client(&self) -> IpcClient {
+        let stream = UnixStream::connect(self.socket_path()).await.unwrap();
+        IpcClient::from_stream(stream, DEFAULT_MAX_MESSAGE_BYTES)
+    }
+
+    /// The binary with `args`, in this daemon's environment.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_vacuum-launcher"));
+        command
+            .args(args)
+            .env("HOME", &self.root)
+            .env("XDG_RUNTIME_DIR", self.root.join("runtime"))
+            .env("XDG_CONFIG_HOME", self.root.join("config"))
+            .env("XDG_STATE_HOME", self.root.join("state"))
+            .env_remove("DBUS_SESSION_BUS_ADDRESS")
+            .env_remove("VACUUM_SOCKET")
+            .env_remove("VACUUM_CONFIG")
+            .stdin(Stdio::null());
+        command
+    }
+
+    /// Runs the command-line client against this daemon.
+    pub fn cli(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    pub fn signal(&self, signal: Signal) {
+        kill(Pid::from_raw(self.child.id() as i32), signal).unwrap();
+    }
+
+    pub fn wait_for_exit(&mut self, timeout: Duration) -> ExitStatus {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "daemon did not exit in time");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    pub fn wait_until(&self, timeout: Duration, mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + timeout;
+        while !condition() {
+            assert!(Instant::now() < deadline, "condition not met in time");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for TestDaemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
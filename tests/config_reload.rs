@@ -0,0 +1,38 @@
+mod common;
+
+use common::TestDaemon;
+use std::time::{Duration, Instant};
+use vacuum_launcher::client::IpcClient;
+use vacuum_launcher::config::Config;
+use vacuum_launcher::daemon::{IpcCommand, IpcResponse};
+use vacuum_launcher::state::VacuumState;
+
+async fn get_state(client: &IpcClient) -> VacuumState {
+    match client.request(IpcCommand::GetState).await.unwrap() {
+        IpcResponse::State(state) => *state,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_config_edits_apply_without_restart() {
+    let daemon = TestDaemon::spawn("hot-reload");
+    let client = daemon.client().await;
+
+    let mut config: Config = toml::from_str(&std::fs::read_to_string(daemon.config_path()).unwrap()).unwrap();
+    config.shortcuts.left_links[0].label = "Forge".to_string();
+    std::fs::write(daemon.config_path(), toml::to_string_pretty(&config).unwrap()).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while get_state(&client).await.launcher_shortcuts.left_links[0].label != "Forge" {
+        assert!(Instant::now() < deadline, "config change was not picked up");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // A broken edit is rejected and the previous config stays active
+    std::fs::write(daemon.config_path(), "[user\nthis is not toml").unwrap();
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    let state = get_state(&client).await;
+    assert_eq!(state.launcher_shortcuts.left_links[0].label, "Forge");
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::TestDaemon;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::pin::Pin;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use vacuum_launcher::dbus::{BUS_NAME, INTERFACE_NAME, OBJECT_PATH};
+use zbus::export::futures_core::Stream;
+use zbus::fdo;
+use zbus::names::InterfaceName;
+use zbus::zvariant::OwnedValue;
+
+/// A private session bus, so the test never touches the developer's.
+struct SessionBus {
+    child: Child,
+    address: String,
+}
+
+impl SessionBus {
+    fn start() -> Option<Self> {
+        let mut child = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(child.stdout.take()?).read_line(&mut address).ok()?;
+        Some(Self { child, address: address.trim().to_string() })
+    }
+}
+
+impl Drop for SessionBus {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn test_commands_and_properties_over_dbus() {
+    let Some(bus) = SessionBus::start() else {
+        eprintln!("dbus-daemon is not installed, skipping");
+        return;
+    };
+    let daemon = TestDaemon::spawn_with("dbus", |_, mut command| {
+        command.env("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+        command
+    });
+
+    let connection = zbus::connection::Builder::address(bus.address.as_str()).unwrap().build().await.unwrap();
+    let bus_proxy = fdo::DBusProxy::new(&connection).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !bus_proxy.name_has_owner(BUS_NAME.try_into().unwrap()).await.unwrap() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("daemon never claimed its bus name");
+
+    let properties = fdo::PropertiesProxy::builder(&connection)
+        .destination(BUS_NAME).unwrap()
+        .path(OBJECT_PATH).unwrap()
+        .build()
+        .await
+        .unwrap();
+    let mut changes = properties.receive_properties_changed().await.unwrap();
+
+    let reply = connection.call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE_NAME), "ShowOverlay", &()).await.unwrap();
+    assert!(reply.body().deserialize::<bool>().unwrap());
+
+    // The collectors publish too, so skip ahead to the overlay change
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let signal = next(&mut changes).await.unwrap();
+            let args = signal.args().unwrap();
+            if let Some(overlay) = args.changed_properties().get("Overlay") {
+                let overlay = HashMap::<String, OwnedValue>::try_from(overlay.try_to_owned().unwrap()).unwrap();
+                assert!(bool::try_from(&overlay["visible"]).unwrap());
+                break;
+            }
+        }
+    })
+    .await
+    .expect("no PropertiesChanged for Overlay");
+
+    let interface = InterfaceName::from_static_str(INTERFACE_NAME).unwrap();
+    let toggles = properties.get(interface, "Toggles").await.unwrap();
+    let toggles = HashMap::<String, OwnedValue>::try_from(toggles).unwrap();
+    assert!(toggles.contains_key("wifi_enabled"));
+
+    let reply = connection.call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE_NAME), "GetState", &()).await.unwrap();
+    let state: serde_json::Value = serde_json::from_str(&reply.body().deserialize::<String>().unwrap()).unwrap();
+    assert_eq!(state["overlay"]["visible"], true);
+
+    // Power actions wait for a token by default; drop it rather than confirm
+    let reply = connection.call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE_NAME), "Reboot", &()).await.unwrap();
+    let token: String = reply.body().deserialize().unwrap();
+    assert!(!token.is_empty());
+    connection.call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE_NAME), "CancelAction", &(token.as_str(),)).await.unwrap();
+
+    let error = connection.call_method(Some(BUS_NAME), OBJECT_PATH, Some(INTERFACE_NAME), "CancelAction", &(token.as_str(),))
+        .await
+        .unwrap_err();
+    let zbus::Error::MethodError(name, _, _) = error else {
+        panic!("expected a method error, got {:?}", error);
+    };
+    assert_eq!(name.as_str(), "dev.onyxdigital.Vacuum1.Error.Failed");
+
+    drop(daemon);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::TestDaemon;
+use nix::sys::signal::Signal;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use vacuum_launcher::daemon::{DaemonStatus, IpcResponse};
+
+fn read_pid(daemon: &TestDaemon) -> Option<u32> {
+    std::fs::read_to_string(daemon.pid_path()).ok()?.trim().parse().ok()
+}
+
+/// Starts `--daemon --replace` next to `daemon` and waits until it owns the PID file.
+fn replace(daemon: &TestDaemon) -> Child {
+    let mut replacement = daemon.command(&["--daemon", "--replace"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let pid = replacement.id();
+    daemon.wait_until(Duration::from_secs(20), || {
+        read_pid(daemon) == Some(pid) && std::os::unix::net::UnixStream::connect(daemon.socket_path()).is_ok()
+    });
+    assert!(replacement.try_wait().unwrap().is_none(), "replacement exited");
+    replacement
+}
+
+/// Runs `command` as a daemon until it serves `socket`.
+fn spawn_beside(daemon: &TestDaemon, mut command: Command, socket: &Path) -> Child {
+    let child = command.stdout(Stdio::null()).stderr(Stdio::null()).spawn().unwrap();
+    daemon.wait_until(Duration::from_secs(10), || std::os::unix::net::UnixStream::connect(socket).is_ok());
+    child
+}
+
+fn status(mut command: Command) -> DaemonStatus {
+    let output = command.output().unwrap();
+    match serde_json::from_slice(&output.stdout) {
+        Ok(IpcResponse::Status(status)) => status,
+        other => panic!("expected a status, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_instances_and_sockets_run_side_by_side() {
+    let daemon = TestDaemon::spawn("instance-named");
+
+    let named_socket = daemon.root.join("runtime/vacuum-launcher-test/vacuum-launcher.sock");
+    let mut named = spawn_beside(&daemon, daemon.command(&["--instance", "test", "--daemon"]), &named_socket);
+    let named_status = status(daemon.command(&["--instance", "test", "status", "--json"]));
+    assert_eq!(named_status.pid, named.id());
+    assert_eq!(named_status.socket_path, named_socket);
+    assert_eq!(named_status.config_path, daemon.root.join("config/vacuum/test/config.toml"));
+
+    let socket = daemon.root.join("side.sock");
+    let config = daemon.root.join("side.toml");
+    let mut command = daemon.command(&["--daemon", "--config", config.to_str().unwrap()]);
+    command.env("VACUUM_SOCKET", &socket);
+    let mut side = spawn_beside(&daemon, command, &socket);
+    assert_eq!(std::fs::read_to_string(daemon.root.join("side.pid")).unwrap(), side.id().to_string());
+    let mut command = daemon.command(&["status", "--json"]);
+    command.env("VACUUM_SOCKET", &socket);
+    let side_status = status(command);
+    assert_eq!(side_status.pid, side.id());
+    assert_eq!(side_status.config_path, config);
+    assert!(config.exists());
+
+    // The default instance is untouched by either
+    assert_eq!(status(daemon.command(&["status", "--json"])).pid, daemon.child.id());
+
+    for child in [&mut named, &mut side] {
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
+
+#[test]
+fn test_second_daemon_refuses_and_replace_takes_over() {
+    let mut daemon = TestDaemon::spawn("instance-replace");
+
+    let output = daemon.command(&["--daemon"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already running"));
+    assert_eq!(read_pid(&daemon), Some(daemon.child.id()));
+
+    let mut replacement = replace(&daemon);
+    // Asked over IPC, so the old daemon shut down cleanly
+    assert!(daemon.wait_for_exit(Duration::from_secs(10)).success());
+    assert_eq!(daemon.cli(&["status"]).status.code(), Some(0));
+
+    replacement.kill().unwrap();
+    replacement.wait().unwrap();
+}
+
+#[test]
+fn test_replace_kills_a_wedged_daemon() {
+    let mut daemon = TestDaemon::spawn("instance-wedged");
+    daemon.signal(Signal::SIGSTOP);
+
+    let mut replacement = replace(&daemon);
+    assert_eq!(daemon.wait_for_exit(Duration::from_secs(10)).signal(), Some(Signal::SIGKILL as i32));
+
+    replacement.kill().unwrap();
+    replacement.wait().unwrap();
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::TestDaemon;
+use std::io::{Read, Write};
+use std::time::Duration;
+use vacuum_launcher::config::Config;
+
+fn spawn_with_metrics(name: &str, listen: impl FnOnce(&std::path::Path) -> String) -> TestDaemon {
+    TestDaemon::spawn_with(name, |root, command| {
+        let mut config = Config::default();
+        config.metrics.enabled = true;
+        config.metrics.listen = listen(root);
+        common::write_config(root, &config);
+        command
+    })
+}
+
+fn scrape(mut stream: impl Read + Write) -> String {
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Scrapes until the first storage collection has landed.
+fn scrape_collected(daemon: &TestDaemon, mut connect: impl FnMut() -> Option<String>) -> String {
+    let mut last = String::new();
+    daemon.wait_until(Duration::from_secs(20), || {
+        last = connect().unwrap_or_default();
+        last.contains("vacuum_section_up{section=\"storage_info\"} 1")
+    });
+    last
+}
+
+fn free_loopback_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+#[tokio::test]
+async fn test_scrape_over_tcp() {
+    let port = free_loopback_port();
+    let daemon = spawn_with_metrics("metrics-tcp", |_| format!("127.0.0.1:{}", port));
+
+    let response = scrape_collected(&daemon, || {
+        std::net::TcpStream::connect(("127.0.0.1", port)).ok().map(scrape)
+    });
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK"));
+    assert!(head.contains("Content-Type: application/openmetrics-text; version=1.0.0; charset=utf-8"));
+    assert!(body.contains("# TYPE vacuum_memory_total_bytes gauge\n"));
+    assert!(body.ends_with("# EOF\n"));
+
+    // df always reports at least the root filesystem
+    let root_total = body.lines()
+        .filter(|line| line.starts_with("vacuum_disk_total_bytes{") && line.contains("mountpoint=\"/\"}"))
+        .find_map(|line| line.rsplit(' ').next()?.parse::<f64>().ok())
+        .unwrap();
+    assert!(root_total > 0.0);
+
+    let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"));
+}
+
+#[tokio::test]
+async fn test_scrape_over_unix_socket() {
+    let mut socket_path = std::path::PathBuf::new();
+    let daemon = spawn_with_metrics("metrics-unix", |root| {
+        socket_path = root.join("metrics.sock");
+        format!("unix:{}", socket_path.display())
+    });
+
+    let response = scrape_collected(&daemon, || {
+        std::os::unix::net::UnixStream::connect(&socket_path).ok().map(scrape)
+    });
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("vacuum_cpu_load_percent "));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::TestDaemon;
+use nix::sys::signal::Signal;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use vacuum_launcher::config::Config;
+use vacuum_launcher::state::StateSection;
+
+fn assert_cleaned_up(socket_path: &Path, pid_path: &Path) {
+    assert!(!socket_path.exists(), "socket was left behind");
+    assert!(!pid_path.exists(), "PID file was left behind");
+}
+
+#[tokio::test]
+async fn test_sigterm_shuts_down_cleanly() {
+    let mut daemon = TestDaemon::spawn("sigterm");
+    assert!(daemon.pid_path().exists());
+
+    daemon.signal(Signal::SIGTERM);
+
+    assert!(daemon.wait_for_exit(Duration::from_secs(10)).success());
+    assert_cleaned_up(&daemon.socket_path(), &daemon.pid_path());
+}
+
+#[tokio::test]
+async fn test_sigint_shuts_down_cleanly() {
+    let mut daemon = TestDaemon::spawn("sigint");
+
+    daemon.signal(Signal::SIGINT);
+
+    assert!(daemon.wait_for_exit(Duration::from_secs(10)).success());
+    assert_cleaned_up(&daemon.socket_path(), &daemon.pid_path());
+}
+
+#[tokio::test]
+async fn test_sighup_reloads_config() {
+    let daemon = TestDaemon::spawn("sighup");
+    let client = daemon.client().await;
+
+    // The default config ships a placeholder API key, so weather counts as enabled
+    let info = client.hello("signal-test").await.unwrap();
+    assert!(info.collectors.contains(&StateSection::WeatherInfo));
+
+    let mut config: Config = toml::from_str(&std::fs::read_to_string(daemon.config_path()).unwrap()).unwrap();
+    config.weather.api_key = None;
+    std::fs::write(daemon.config_path(), toml::to_string_pretty(&config).unwrap()).unwrap();
+
+    daemon.signal(Signal::SIGHUP);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let info = client.hello("signal-test").await.unwrap();
+        if !info.collectors.contains(&StateSection::WeatherInfo) {
+            break;
+        }
+        assert!(Instant::now() < deadline, "config was not reloaded");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::TestDaemon;
+use nix::sys::signal::Signal;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// Collects datagrams from a fake `NOTIFY_SOCKET` until `expected` shows up.
+fn wait_for_notification(socket: &UnixDatagram, expected: &str, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; 256];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        assert!(!remaining.is_zero(), "never received {}", expected);
+        socket.set_read_timeout(Some(remaining)).unwrap();
+        let Ok(len) = socket.recv(&mut buf) else { continue };
+        if std::str::from_utf8(&buf[..len]).unwrap().lines().any(|line| line == expected) {
+            return;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_notifies_ready_and_pings_watchdog() {
+    let mut notify_socket = None;
+    let _daemon = TestDaemon::spawn_with("sd-notify", |root, mut command| {
+        let path = root.join("notify.sock");
+        notify_socket = Some(UnixDatagram::bind(&path).unwrap());
+        command.env("NOTIFY_SOCKET", &path).env("WATCHDOG_USEC", "400000");
+        command
+    });
+    let notify_socket = notify_socket.unwrap();
+
+    wait_for_notification(&notify_socket, "READY=1", Duration::from_secs(20));
+    wait_for_notification(&notify_socket, "WATCHDOG=1", Duration::from_secs(5));
+    wait_for_notification(&notify_socket, "WATCHDOG=1", Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_uses_socket_passed_by_systemd() {
+    let mut listener = None;
+    let mut daemon = TestDaemon::spawn_with("socket-activation", |root, command| {
+        let dir = root.join("runtime/vacuum-launcher");
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = UnixListener::bind(dir.join("vacuum-launcher.sock")).unwrap();
+        let fd = socket.as_raw_fd();
+        listener = Some(socket);
+
+        // Mimic systemd: the socket arrives as fd 3 and LISTEN_PID names the daemon itself
+        let mut activated = Command::new("sh");
+        activated
+            .arg("-c")
+            .arg("LISTEN_PID=$$ LISTEN_FDS=1 exec \"$0\" \"$@\"")
+            .arg(command.get_program())
+            .args(command.get_args());
+        unsafe {
+            activated.pre_exec(move || {
+                if fd == 3 {
+                    nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::empty()))?;
+                } else {
+                    nix::unistd::dup2(fd, 3)?;
+                }
+                Ok(())
+            });
+        }
+        activated
+    });
+
+    let client = daemon.client().await;
+    client.hello("socket-activation-test").await.unwrap();
+
+    daemon.signal(Signal::SIGTERM);
+    assert!(daemon.wait_for_exit(Duration::from_secs(10)).success());
+
+    // The socket belongs to systemd, so it survives for the next activation
+    assert!(daemon.socket_path().exists());
+    assert!(!daemon.pid_path().exists());
+    drop(listener);
+}